@@ -0,0 +1,103 @@
+use crate::config::ButtonBinding;
+use crate::input_handler::{Button, ControllerEvent};
+use serde::{Serialize, Deserialize};
+
+//what the buttons do, bound to them by conf.button_mapping
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum Action {
+    NEXT_MODE,
+    PREV_MODE,
+    SIT_STAND,
+    EXIT, //sits down, releases the servos and ends the program
+    FASTER, //changes the animation timestep
+    SLOWER,
+    STANCE_HIGHER,
+    STANCE_LOWER,
+    STANCE_WIDER,
+    STANCE_NARROWER,
+    NEXT_GAIT,
+    PREV_GAIT,
+    RESET_POSE,
+    SHIFT_BODY, //while held the sticks shift and tilt the body instead of walking
+    TURN_BODY, //while held the left stick sets the yaw and height of the body
+    NEXT_LEG,
+    PREV_LEG,
+    PLAY_PAUSE,
+    TOGGLE_LOOP,
+    TAP_TEMPO,
+    DANCE_FASTER,
+    DANCE_SLOWER,
+}
+
+//the actions of one controller event. An action is held while all of its buttons are pressed and
+//triggered when one of them has just been pressed. Triggering a combination hides the bindings of
+//fewer buttons (L + UP doesn't trigger UP), and bindings of the active mode hide bindings of all
+//modes that use the same buttons
+#[derive(Debug, Default, Clone)]
+pub struct Actions {
+    triggered: Vec<Action>,
+    held: Vec<Action>,
+}
+
+impl Actions {
+    pub fn resolve(event: &ControllerEvent, bindings: &[ButtonBinding], mode: &str) -> Self {
+        let is_down = |b: &Button| event.pressed(*b) || event.triggered(*b);
+        let active = bindings.iter()
+            .filter(|b| b.mode.as_deref().is_none_or(|m| m == mode))
+            .filter(|b| !b.buttons.is_empty() && b.buttons.iter().all(is_down))
+            .collect::<Vec<_>>();
+        let active = active.iter()
+            .filter(|b| !active.iter().any(|other| other.mode.is_some() && b.mode.is_none() && same_buttons(other, b)))
+            .collect::<Vec<_>>();
+
+        let triggered = active.iter()
+            .filter(|b| b.buttons.iter().any(|button| event.triggered(*button)))
+            .collect::<Vec<_>>();
+        Self {
+            triggered: triggered.iter()
+                .filter(|b| !triggered.iter().any(|other| is_combination_of(other, b)))
+                .map(|b| b.action)
+                .collect(),
+            held: active.iter().map(|b| b.action).collect(),
+        }
+    }
+
+    pub fn triggered(&self, action: Action) -> bool {
+        self.triggered.contains(&action)
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+}
+
+//the buttons a mode binds itself (the bindings with its name), they are reported in the telemetry
+//and hide the bindings of all modes with the same buttons while the mode is active
+pub fn consumed_buttons(bindings: &[ButtonBinding], mode: &str) -> Vec<Button> {
+    Button::all().iter().copied()
+        .filter(|button| bindings.iter().any(|b| b.mode.as_deref() == Some(mode) && b.buttons.contains(button)))
+        .collect()
+}
+
+fn same_buttons(a: &ButtonBinding, b: &ButtonBinding) -> bool {
+    a.buttons.iter().all(|x| b.buttons.contains(x)) && b.buttons.iter().all(|x| a.buttons.contains(x))
+}
+
+//true if the combination contains all buttons of the binding and more
+fn is_combination_of(combination: &ButtonBinding, binding: &ButtonBinding) -> bool {
+    binding.buttons.iter().all(|b| combination.buttons.contains(b)) && !same_buttons(combination, binding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn modes_consume_the_buttons_they_bind() {
+        let conf = Config::default();
+        assert_eq!(consumed_buttons(&conf.button_mapping, "dance"), vec![Button::A, Button::Y, Button::R, Button::LEFT, Button::RIGHT]);
+        assert_eq!(consumed_buttons(&conf.button_mapping, "look_at"), vec![]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{Angle, Isometry3, Rotation3, Vector3};
+use crate::config::Config;
+use crate::hexapod::Hexapod;
+use serde::{Serialize, Deserialize};
+
+//offset of the body relative to its neutral standing pose. The front of the body points
+//towards +y, so pitch tilts the front up/down (x axis) and roll tilts the sides (y axis)
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct BodyPose {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64, //relative to the standing height
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+impl BodyPose {
+    pub fn new(x: f64, y: f64, z: f64, roll: f64, pitch: f64, yaw: f64) -> Self {
+        Self { x, y, z, roll, pitch, yaw }
+    }
+
+    pub fn neutral() -> Self {
+        Self::default()
+    }
+
+    pub fn translation(&self) -> Vector3 { Vector3::new(self.x, self.y, self.z) }
+    pub fn rotation(&self) -> Vector3 { Vector3::new(self.roll, self.pitch, self.yaw) }
+
+    pub fn to_isometry(&self, height: f64) -> Isometry3 {
+        let rotation = Rotation3::euler(Angle::radians(self.pitch), Angle::radians(self.roll), Angle::radians(self.yaw));
+        Isometry3::new(rotation, Vector3::new(self.x, self.y, height + self.z))
+    }
+
+    //moves the pose one animation step towards the target, returns true once it is reached
+    pub fn step_towards(&mut self, target: &BodyPose, conf: &Config) -> bool {
+        let translation = (target.translation() - self.translation()).with_max_length(conf.walking_translating_resolution*0.5);
+        self.x += translation.x;
+        self.y += translation.y;
+        self.z += translation.z;
+
+        let rotation = (target.rotation() - self.rotation()).with_max_length(conf.walking_rotating_resolution.radians);
+        self.roll += rotation.x;
+        self.pitch += rotation.y;
+        self.yaw += rotation.z;
+
+        self.approx_eq(target)
+    }
+
+    //adds one axis after the other, each limited to the largest value the legs can still reach
+    pub fn clamp_reachable(&self, hexapod: &Hexapod, height: f64) -> BodyPose {
+        let reachable = |pose: &BodyPose| hexapod.is_reachable(&pose.to_isometry(height));
+        if reachable(self) {
+            return *self;
+        }
+
+        let axes: [fn(&mut BodyPose) -> &mut f64; 6] = [
+            |p| &mut p.z, |p| &mut p.x, |p| &mut p.y, |p| &mut p.roll, |p| &mut p.pitch, |p| &mut p.yaw
+        ];
+        let mut desired = *self;
+        let mut result = BodyPose::neutral();
+        for axis in axes.iter() {
+            let value = *axis(&mut desired);
+            *axis(&mut result) = value;
+            if reachable(&result) {
+                continue;
+            }
+
+            let (mut min, mut max) = (0.0, 1.0);
+            for _ in 0..10 {
+                let scale = (min + max) / 2.0;
+                *axis(&mut result) = value * scale;
+                if reachable(&result) { min = scale } else { max = scale }
+            }
+            *axis(&mut result) = value * min;
+        }
+        result
+    }
+
+    pub fn approx_eq(&self, other: &BodyPose) -> bool {
+        (self.translation() - other.translation()).length() < 0.1 && (self.rotation() - other.rotation()).length() < 0.001
+    }
+}
@@ -0,0 +1,28 @@
+use crate::Point3;
+use crate::body_pose::BodyPose;
+use crate::modes::gait::{GaitType, WalkingInput};
+use serde::{Serialize, Deserialize};
+
+//what the robot should do, independent of where the request comes from. The modes translate the
+//controller events into commands, scripts and network clients send them to InputHandler::handle_command.
+//A command stays in effect until another one replaces it, e.g. the robot keeps walking after WALK
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum Command {
+    //fractions of the maximum speed from -1 to 1: vx to the right, vy forward and omega counterclockwise
+    WALK { vx: f64, vy: f64, omega: f64 },
+    BODY_POSE(BodyPose), //target offset of the body from its neutral pose
+    GAIT(GaitType),
+    MODE(String), //one of the names in conf.modes
+    LOOK_AT(Point3), //point in world coordinates the front of the body is turned towards
+    STAND,
+    SIT,
+    STOP, //stops walking and returns the body to its neutral pose
+}
+
+impl Command {
+    pub(crate) fn walk(input: &WalkingInput) -> Self {
+        let translation = input.translation();
+        Command::WALK { vx: translation.x, vy: translation.y, omega: input.rot() }
+    }
+}
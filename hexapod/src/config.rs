@@ -1,15 +1,58 @@
-use crate::Angle;
+use crate::{Angle, Point3, Vector3};
+use std::collections::HashMap;
+use std::error::Error;
 use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use serde::{Serialize, Deserialize};
 use splines::{Interpolation, Key, Spline};
+use crate::actions::Action;
+use crate::body_pose::BodyPose;
+use crate::hexapod::Foot;
+use crate::input_handler::Button;
+use crate::utils::clamp_abs;
+use crate::leg::Leg;
 
+//every field can be overridden by a json file (see Config::load), missing fields keep their defaults.
+//Angles are given as {"radians": ...}
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub walking_translating_resolution: f64,
     pub walking_rotating_resolution: Angle,
     pub walking_max_step_dist: f64,
     pub walking_max_rotation_dist: Angle,
     pub anim_timestep: f64,
+    pub tick_period: f64, //seconds per control tick, everything that is timed in seconds counts ticks of this length
     pub walking_step_height: f64,
+    #[serde(skip)]
     pub foot_height: Spline<f64, f64>, //maps the current foot progress to the desired foot height
+    pub body_pose_range: BodyPose, //max offset per axis when posing the body with the sticks
+    pub stance: Stance,
+    pub stance_adjust_step: f64,
+    pub look_at_distance: f64, //initial distance of the target in front of the robot
+    pub look_at_target_speed: f64, //how far the sticks move the target per animation step
+    pub look_at_walk_gain: f64, //walking speed per radian the target is outside of the body pose range
+    pub single_leg_lift_height: f64,
+    pub single_leg_speed: f64, //how far the sticks move the lifted foot per animation step
+    pub dance_file: String,
+    pub dance_bpm: Option<f64>, //plays the dance at this tempo instead of the one in the file
+    pub modes: Vec<String>, //names of the modes ZL/ZR cycle through, the first one is active after standing up
+    pub mode_blend_time: f64, //seconds, how long a mode change blends from the old mode's pose to the new one, 0 switches instantly
+    pub controller_timeout: f64, //seconds without controller events until the link counts as lost
+    pub controller_fade_time: f64, //seconds over which the sticks fade to zero after the link was lost
+    pub input: String, //where the controller events come from, e.g. "tcp-server:0.0.0.0:8765" (see io_utils::input::parse_source)
+    pub controller_protocol: ControllerProtocol, //used by the tcp and udp input sources
+    pub gamepad: GamepadMapping, //used by the evdev input source
+    pub input_shaping: InputShaping, //applied to the sticks and triggers before the modes see them
+    pub button_mapping: Vec<ButtonBinding>, //replaces the whole default table when given
+    pub telemetry_address: String, //where the telemetry clients connect to
+    pub websocket_address: String, //telemetry, commands and config patches over WebSocket
+    pub foxglove_address: String, //Foxglove Studio connects here with the Foxglove WebSocket protocol
+    pub foxglove_publish_rate: f64, //Hz, Foxglove doesn't need every control tick
+    pub telemetry_log: Option<String>, //directory the telemetry of every control tick is logged to
+    pub telemetry_log_file_size: u64, //bytes after which a new log file is started
+    pub telemetry_log_files: usize, //the oldest log files are deleted when there are more
 }
 
 impl Default for Config {
@@ -27,12 +70,249 @@ impl Default for Config {
             walking_max_step_dist: 50.0,
             walking_max_rotation_dist: Angle::degrees(35.0),
             anim_timestep: 0.03,
+            tick_period: 0.01,
             walking_step_height: 15.0,
             foot_height: Spline::from_vec(foot_progress_keyframes),
+            body_pose_range: BodyPose::new(15.0, 15.0, 25.0, 0.25, 0.25, 0.25),
+            stance: Stance::default(),
+            stance_adjust_step: 5.0,
+            look_at_distance: 300.0,
+            look_at_target_speed: 5.0,
+            look_at_walk_gain: 2.0,
+            single_leg_lift_height: 40.0,
+            single_leg_speed: 3.0,
+            dance_file: String::from("dances/example.json"),
+            dance_bpm: None,
+            modes: ["gait", "move_body", "look_at", "single_leg", "dance"].iter().map(|m| m.to_string()).collect(),
+            mode_blend_time: 0.3,
+            controller_timeout: 0.5,
+            controller_fade_time: 1.0,
+            input: String::from("tcp-client:pop-os.fritz.box:8765"),
+            controller_protocol: ControllerProtocol::default(),
+            gamepad: GamepadMapping::default(),
+            input_shaping: InputShaping::default(),
+            button_mapping: default_button_mapping(),
+            telemetry_address: String::from("0.0.0.0:8766"),
+            websocket_address: String::from("0.0.0.0:8767"),
+            foxglove_address: String::from("0.0.0.0:8768"),
+            foxglove_publish_rate: 30.0,
+            telemetry_log: None,
+            telemetry_log_file_size: 20_000_000,
+            telemetry_log_files: 10,
         }
     }
 }
 
+impl Config {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    //overrides the fields given in the json object, objects inside of it are merged as well.
+    //Changes to modes, input and the addresses only take effect after a restart
+    pub fn patch(&mut self, patch: &serde_json::Value) -> Result<(), Box<dyn Error>> {
+        let mut value = serde_json::to_value(&*self)?;
+        if let (Some(fields), Some(patch)) = (value.as_object(), patch.as_object()) {
+            if let Some(unknown) = patch.keys().find(|k| !fields.contains_key(*k)) {
+                return Err(format!("unknown config field: {}", unknown).into());
+            }
+        }
+        merge(&mut value, patch);
+        let patched: Config = serde_json::from_value(value)?;
+        if patched.stance != self.stance && !patched.stance.is_reachable(patched.walking_step_height) {
+            return Err(format!("stance out of reach: {:?}", patched.stance).into());
+        }
+        *self = Config { foot_height: self.foot_height.clone(), ..patched };
+        Ok(())
+    }
+}
+
+fn merge(value: &mut serde_json::Value, patch: &serde_json::Value) {
+    match (value, patch) {
+        (serde_json::Value::Object(value), serde_json::Value::Object(patch)) => {
+            for (key, field) in patch {
+                merge(value.entry(key.clone()).or_insert(serde_json::Value::Null), field);
+            }
+        },
+        (value, patch) => *value = patch.clone(),
+    }
+}
+
+//LEGACY: the bare 32 byte messages of older controller clients, FRAMED: the messages wrapped in
+//frames with a sequence number and checksum (see io_utils::controller)
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+pub enum ControllerProtocol {
+    LEGACY,
+    #[default]
+    FRAMED,
+}
+
+//maps a gamepad read through Linux evdev onto the ControllerEvent. The names are evdev codes,
+//a leading '-' inverts a stick axis. Axes can also act as buttons, "+ABS_HAT0X" is pressed
+//when the axis is past half of its positive range and "-ABS_HAT0X" on the negative side
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GamepadMapping {
+    pub lx: String,
+    pub ly: String,
+    pub rx: String,
+    pub ry: String,
+    pub lt: Option<String>,
+    pub rt: Option<String>,
+    pub dpad_x: Option<String>,
+    pub dpad_y: Option<String>,
+    pub buttons: HashMap<String, Button>,
+}
+
+//the layout of the Linux gamepad drivers (xpad, hid-nintendo, ...), the face buttons are
+//named after their position like on a Switch controller
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        let buttons = [
+            ("BTN_EAST", Button::A), ("BTN_SOUTH", Button::B), ("BTN_NORTH", Button::X), ("BTN_WEST", Button::Y),
+            ("BTN_TL", Button::L), ("BTN_TR", Button::R), ("BTN_TL2", Button::ZL), ("BTN_TR2", Button::ZR),
+            ("+ABS_Z", Button::ZL), ("+ABS_RZ", Button::ZR),
+            ("BTN_DPAD_UP", Button::UP), ("BTN_DPAD_DOWN", Button::DOWN), ("BTN_DPAD_LEFT", Button::LEFT), ("BTN_DPAD_RIGHT", Button::RIGHT),
+            ("-ABS_HAT0Y", Button::UP), ("+ABS_HAT0Y", Button::DOWN), ("-ABS_HAT0X", Button::LEFT), ("+ABS_HAT0X", Button::RIGHT),
+        ];
+        Self {
+            lx: String::from("ABS_X"),
+            ly: String::from("-ABS_Y"),
+            rx: String::from("ABS_RX"),
+            ry: String::from("-ABS_RY"),
+            lt: Some(String::from("ABS_Z")),
+            rt: Some(String::from("ABS_RZ")),
+            dpad_x: Some(String::from("ABS_HAT0X")),
+            dpad_y: Some(String::from("-ABS_HAT0Y")),
+            buttons: buttons.iter().map(|(code, button)| (code.to_string(), *button)).collect(),
+        }
+    }
+}
+
+//binds a button or a combination of buttons to an action (see actions.rs),
+//e.g. {"buttons": ["L", "UP"], "action": "STANCE_HIGHER"} or {"buttons": ["A"], "action": "RESET_POSE", "mode": "gait"}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ButtonBinding {
+    pub buttons: Vec<Button>,
+    pub action: Action,
+    #[serde(default)]
+    pub mode: Option<String>, //the binding only applies in this mode, otherwise in all of them
+}
+
+fn default_button_mapping() -> Vec<ButtonBinding> {
+    use Action::*;
+    use Button::*;
+    let bindings: [(&[Button], Action, Option<&str>); 23] = [
+        (&[ZR], NEXT_MODE, None), (&[ZL], PREV_MODE, None), (&[X], SIT_STAND, None), (&[B], EXIT, None),
+        (&[UP], FASTER, None), (&[DOWN], SLOWER, None),
+        (&[L, UP], STANCE_HIGHER, None), (&[L, DOWN], STANCE_LOWER, None),
+        (&[L, RIGHT], STANCE_WIDER, None), (&[L, LEFT], STANCE_NARROWER, None),
+        (&[RIGHT], NEXT_GAIT, Some("gait")), (&[LEFT], PREV_GAIT, Some("gait")), (&[A], RESET_POSE, Some("gait")),
+        (&[L], SHIFT_BODY, Some("gait")), (&[R], TURN_BODY, Some("gait")),
+        (&[R], TURN_BODY, Some("move_body")),
+        (&[RIGHT], NEXT_LEG, Some("single_leg")), (&[LEFT], PREV_LEG, Some("single_leg")),
+        (&[A], PLAY_PAUSE, Some("dance")), (&[R], TOGGLE_LOOP, Some("dance")), (&[Y], TAP_TEMPO, Some("dance")),
+        (&[RIGHT], DANCE_FASTER, Some("dance")), (&[LEFT], DANCE_SLOWER, Some("dance")),
+    ];
+    bindings.iter().map(|(buttons, action, mode)| ButtonBinding {
+        buttons: buttons.to_vec(),
+        action: *action,
+        mode: mode.map(|m| m.to_string()),
+    }).collect()
+}
+
+//per-axis settings of the input shaping (see input_shaping.rs)
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone)]
+#[serde(default)]
+pub struct InputShaping {
+    pub lx: AxisShaping,
+    pub ly: AxisShaping,
+    pub rx: AxisShaping,
+    pub ry: AxisShaping,
+    pub lt: AxisShaping,
+    pub rt: AxisShaping,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(default)]
+pub struct AxisShaping {
+    pub dead_zone: f64, //fraction of the range that reads as 0, the rest is scaled to start at 0
+    pub expo: f64, //0 is linear, 1 is fully cubic and gives the finest control around the center
+    pub invert: bool,
+    pub max_rate: f64, //largest change per second, 0 for no limit
+}
+
+impl Default for AxisShaping {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.1,
+            expo: 0.3,
+            invert: false,
+            max_rate: 0.0,
+        }
+    }
+}
+
+//ride height of the body and footprint of the feet while standing
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct Stance {
+    pub height: f64,
+    pub width: f64, //moves all feet sideways away from the body
+}
+
+impl Default for Stance {
+    fn default() -> Self {
+        Self {
+            height: BODY_DIST_TO_GROUND,
+            width: 0.0,
+        }
+    }
+}
+
+impl Stance {
+    //body resting on the ground with the feet spread out far enough to be reachable
+    pub fn seated() -> Self {
+        Self {
+            height: 0.0,
+            width: SEATED_STANCE_WIDTH,
+        }
+    }
+
+    //position of a foot while seated, relative to the origin. The robot boots with its feet here
+    //and the sit down sequence ends here
+    pub fn seated_foot_pos(foot: Foot) -> Point3 {
+        Self::seated().foot_pos(foot) + Vector3::new(0.0, 0.0, SEATED_FOOT_HEIGHT)
+    }
+
+    //position of the foot relative to the center of the robot
+    pub fn foot_pos(&self, foot: Foot) -> Point3 {
+        let mut pos = Point3::new(foot.get_mult_x() * (CENTER_TO_FOOT_X + self.width), foot.get_mult_y() * CENTER_TO_FOOT_Y, 0.0);
+        if foot.is_middle() {
+            pos.x += MIDDLE_FOOT_OFFSET * foot.get_mult_x();
+        }
+        pos
+    }
+
+    //moves the height and width by at most step mm towards the target
+    pub fn step_towards(&mut self, target: &Stance, step: f64) {
+        self.height += clamp_abs(target.height - self.height, step);
+        self.width += clamp_abs(target.width - self.width, step);
+    }
+
+    //every foot has to be reachable while standing and at the highest point of a step
+    pub fn is_reachable(&self, step_height: f64) -> bool {
+        Foot::all().iter().all(|foot| {
+            let joint = foot.leg_joint_pos().to_3d() + Vector3::new(0.0, 0.0, self.height);
+            let rel_pos = self.foot_pos(*foot) - joint;
+            [0.0, step_height].iter().all(|h| {
+                Leg::can_reach(rel_pos + Vector3::new(0.0, 0.0, *h), foot.leg_joint_orientation())
+            })
+        })
+    }
+}
+
 //HEXAPOD DIMENSIONS
 pub const BODY_WIDTH: f64 = 78.0;
 pub const BODY_HEIGHT: f64 = 140.0;
@@ -44,14 +324,28 @@ pub const LOWER_LEG_LENGTH: f64 = 92.0;
 
 pub const CORNER_JOINT_ROTATION: f64 = PI/4.0;
 
+// the servos are always in the order [hip_xy, hip_z, knee]
+pub const SERVO_LIMITS: [(f64, f64); 3] = [(-PI*0.25, PI*0.25), (-PI*0.33, PI*0.4), (-PI*0.28, PI*0.45)];
+pub const SERVO_TORQUE: f64 = 0.18; //Nm, only used for the URDF
+pub const SERVO_SPEED: f64 = 10.0; //rad/s, only used for the URDF
+
+//rough sizes for the collision shapes of the URDF
+pub const BODY_THICKNESS: f64 = 30.0;
+pub const LEG_THICKNESS: f64 = 20.0;
+
 //INITIAL POSITION
 pub const CENTER_TO_FOOT_X: f64 = 85.0;
 pub const CENTER_TO_FOOT_Y: f64 = 130.0;
 pub const MIDDLE_FOOT_OFFSET: f64 = 50.0;
 pub const BODY_DIST_TO_GROUND: f64 = 70.0;
 
+//SEATED POSITION (at boot and before the servos are released)
+pub const SEATED_STANCE_WIDTH: f64 = 70.0;
+pub const SEATED_FOOT_HEIGHT: f64 = 15.0;
+
 //INPUT HANDLING
-pub const INPUT_MIN_MAG: f64 = 0.1;
-pub const INPUT_FINALIZED_DELAY: u128 = 250; //ms
+pub const INPUT_MIN_MAG: f64 = 0.02; //shaped inputs below this don't start walking
+pub const INPUT_SIMILAR_MAG: f64 = 0.1; //smaller input changes don't restart INPUT_FINALIZED_DELAY, e.g. stick jitter
+pub const INPUT_FINALIZED_DELAY: f64 = 0.25; //s
 
 
@@ -1,10 +1,12 @@
-use crate::{Point2, Isometry2, Vector2, Point3, Isometry3, Vector3, Transform3, Rotation3, Angle};
-use crate::config::{CENTER_TO_FOOT_X, CENTER_TO_FOOT_Y, MIDDLE_FOOT_OFFSET, BODY_WIDTH, BODY_HEIGHT, BODY_WIDTH_MIDDLE, BODY_DIST_TO_GROUND, CORNER_JOINT_ROTATION};
-use std::ops::{Deref, DerefMut};
-use crate::telemetry::TelemetryMessage;
+use crate::{Point2, Isometry2, Vector2, Point3, Isometry3, Transform3, Rotation3, Angle};
+use crate::config::{BODY_WIDTH, BODY_HEIGHT, BODY_WIDTH_MIDDLE, CORNER_JOINT_ROTATION, Stance};
+use crate::telemetry::{LinkState, LoopTiming, TelemetryMessage, TransformTelemetry};
+use crate::input_handler::ControllerEvent;
 use crate::leg::Leg;
 use std::f64::consts::PI;
+use serde::Deserialize;
 
+#[allow(non_snake_case)]
 pub struct Hexapod{
     pub origin: Isometry2, //2d position and rotation of the center
     pub bodyTransform: Isometry3, //3d body offset and rotation relative to origin
@@ -14,6 +16,7 @@ pub struct Hexapod{
 }
 
 impl Hexapod{
+    //starts out seated, the InputHandler makes it stand up on request
     pub fn new() -> Self{
         let mut this = Self{
             origin: Isometry2::identity(),
@@ -22,10 +25,10 @@ impl Hexapod{
             legJoints:  [Vector2::zero(); 6],
             feet: [Point3::zero(); 6],
         };
-        this.bodyTransform.translation.z = BODY_DIST_TO_GROUND;
+        this.bodyTransform.translation.z = 0.0;
         this.bodyTransform.rotation = Rotation3::identity();
         for foot in Foot::all() {
-            this.set_abs_foot_pos(foot, foot.initial_foot_pos());
+            this.set_abs_foot_pos(foot, Stance::seated_foot_pos(foot));
             this.legJoints[foot as usize] = foot.leg_joint_pos();
         }
         this
@@ -33,7 +36,7 @@ impl Hexapod{
 
     //TODO: remove getter and setter because legs not only need to be updated when the foot pos changes
     pub fn get_abs_foot_pos(&self, foot: Foot) -> Point3{
-        self.feet[foot as usize].clone()
+        self.feet[foot as usize]
     }
 
     pub fn set_abs_foot_pos(&mut self, foot: Foot, pos: Point3){
@@ -49,7 +52,7 @@ impl Hexapod{
     }
 
     pub fn update_all_legs(&mut self) -> Transform3 {
-        let transform = self.bodyTransform.to_transform().then(&self.origin.get_transform().to_3d());
+        let transform = self.body_to_world(&self.bodyTransform);
         let inv_transform = transform.inverse().unwrap();
         for foot in Foot::all(){
             let mut rel_pos = inv_transform.transform_point3d(self.feet[foot as usize]).unwrap();
@@ -59,13 +62,58 @@ impl Hexapod{
         transform
     }
 
+    //checks if every foot stays reachable when the body is moved to the given transform (relative to origin)
+    pub fn is_reachable(&self, body_transform: &Isometry3) -> bool {
+        let inv_transform = self.body_to_world(body_transform).inverse().unwrap();
+        Foot::all().iter().all(|foot| {
+            let mut rel_pos = inv_transform.transform_point3d(self.feet[foot.id()]).unwrap();
+            rel_pos -= self.legJoints[foot.id()].to_3d();
+            Leg::can_reach(rel_pos, foot.leg_joint_orientation())
+        })
+    }
+
+    pub fn get_pose(&self) -> HexapodPose {
+        HexapodPose {
+            origin: self.origin,
+            bodyTransform: self.bodyTransform,
+            feet: self.feet,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: &HexapodPose) {
+        self.origin = pose.origin;
+        self.bodyTransform = pose.bodyTransform;
+        self.feet = pose.feet;
+    }
+
+    fn body_to_world(&self, body_transform: &Isometry3) -> Transform3 {
+        body_transform.to_transform().then(&self.origin.get_transform().to_3d())
+    }
+
     pub fn get_telemetry(&mut self) -> TelemetryMessage {
         let transform = self.update_all_legs();
+        let angles = self.get_angles();
+        let rotation = self.bodyTransform.rotation;
         TelemetryMessage {
+            //filled in by the InputHandler and the main loop
+            sequence: 0,
+            timestamp: 0.0,
+            mode: String::new(),
+            link: LinkState::default(),
+            gait: None,
+            input: ControllerEvent::default(),
+            consumed_buttons: Vec::new(),
+            timing: LoopTiming::default(),
+
             center: transform.transform_point2d(Point2::origin()).unwrap().to_array(),
             rotation: transform.transform_vector2d(Vector2::new(1., 0.)).angle_from_x_axis().radians,
+            body: TransformTelemetry {
+                translation: self.bodyTransform.translation.to_array(),
+                rotation: [rotation.i, rotation.j, rotation.k, rotation.r],
+            },
             legs: Foot::all().map(|f| {self.legs[f as usize].get_telemetry(self.legJoints[f as usize], &transform) }),
-            angles: self.get_angles(),
+            angles,
+            unreachable: Foot::all().map(|f| angles[f as usize * 3].is_none()),
         }
     }
 
@@ -90,7 +138,36 @@ impl Hexapod{
     }*/
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+//everything the modes move, used to blend from one mode to the next
+#[derive(Copy, Clone)]
+#[allow(non_snake_case)]
+pub struct HexapodPose {
+    pub origin: Isometry2,
+    pub bodyTransform: Isometry3,
+    pub feet: [Point3; 6], //absolute positions
+}
+
+impl HexapodPose {
+    pub fn lerp(&self, other: &HexapodPose, t: f64) -> Self {
+        Self {
+            origin: Isometry2::lerp(&self.origin, &other.origin, t),
+            bodyTransform: Isometry3::new(
+                self.bodyTransform.rotation.slerp(&other.bodyTransform.rotation, t),
+                self.bodyTransform.translation.lerp(other.bodyTransform.translation, t),
+            ),
+            feet: Foot::all().map(|f| self.feet[f.id()].lerp(other.feet[f.id()], t)),
+        }
+    }
+}
+
+impl Default for Hexapod {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[allow(non_camel_case_types)]
 pub enum Foot{
     RIGHT_FRONT,
     RIGHT_MIDDLE,
@@ -114,10 +191,7 @@ impl Foot{
     }
 
     pub fn is_even(&self) -> bool{
-        match self {
-            Self::RIGHT_FRONT | Self::RIGHT_BACK | Self::LEFT_MIDDLE => true,
-            _ => false,
-        }
+        matches!(self, Self::RIGHT_FRONT | Self::RIGHT_BACK | Self::LEFT_MIDDLE)
     }
 
     pub fn is_right(&self) -> bool {
@@ -140,10 +214,13 @@ impl Foot{
     }
 
     pub fn is_middle(&self) -> bool {
-        match self {
-             Self::RIGHT_MIDDLE | Self::LEFT_MIDDLE => true,
-            _ => false
-        }
+        matches!(self, Self::RIGHT_MIDDLE | Self::LEFT_MIDDLE)
+    }
+
+    //the names of the three servo joints of the leg from the body outwards, e.g. "right_front_hip"
+    pub fn joint_names(&self) -> [String; 3] {
+        let leg = format!("{:?}", self).to_lowercase();
+        ["hip", "upper_leg", "lower_leg"].map(|joint| format!("{}_{}", leg, joint))
     }
 
     pub fn leg_joint_pos(&self) -> Vector2{
@@ -153,14 +230,6 @@ impl Foot{
         )
     }
 
-    pub fn initial_foot_pos(&self) -> Point3 {
-        let mut pos = Point3::new(self.get_mult_x() * CENTER_TO_FOOT_X, self.get_mult_y() * CENTER_TO_FOOT_Y, 0.0);
-        if self.is_middle() {
-            pos.x += MIDDLE_FOOT_OFFSET * self.get_mult_x();
-        }
-        pos
-    }
-
     pub fn leg_joint_orientation(&self) -> Vector2 {
         let mut angle = self.get_mult_y()*CORNER_JOINT_ROTATION;
         if !self.is_right() {
@@ -169,6 +238,15 @@ impl Foot{
         Vector2::from_angle_and_length(Angle::radians(angle), 1.0)
     }
 
+    //the feet are ordered clockwise around the body
+    pub fn next(&self) -> Foot {
+        Self::all()[(self.id() + 1) % 6]
+    }
+
+    pub fn prev(&self) -> Foot {
+        Self::all()[(self.id() + 5) % 6]
+    }
+
     pub fn id(&self) -> usize {
         *self as usize
     }
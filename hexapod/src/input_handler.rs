@@ -1,12 +1,19 @@
-use crate::config::Config;
-use crate::hexapod::Hexapod;
-use crate::modes::gait::GaitEngine;
+use crate::actions::{self, Action, Actions};
+use crate::command::Command;
+use crate::config::{Config, Stance};
+use crate::body_pose::BodyPose;
+use crate::hexapod::{Hexapod, HexapodPose, Foot};
+use crate::input_shaping::InputShaper;
 use crate::modes::idle::IdleMode;
 use crate::modes::Mode;
-use crate::modes::move_body::MoveBodyMode;
+use crate::modes::registry::ModeRegistry;
+use crate::telemetry::TelemetryMessage;
+use crate::utils::smoothstep;
+use serde::{Serialize, Deserialize};
 //use crate::modes::walking::WalkingEngine;
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, Default, Debug, Copy, Clone)]
+#[serde(default)]
 pub struct ControllerEvent{
     pub timestamp: usize,
     pub lx: f64,
@@ -15,8 +22,13 @@ pub struct ControllerEvent{
     pub ry: f64,
     pub pressed: [bool; 12],
     pub triggered: [bool; 12],
+    pub lt: f64, //analog triggers from 0 to 1, not every controller has them
+    pub rt: f64,
+    pub dpad_x: f64, //analog d-pad, the digital one is in pressed/triggered
+    pub dpad_y: f64,
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Button{
     A, B, X, Y,
     L, R, ZL, ZR,
@@ -27,22 +39,53 @@ pub struct InputHandler{
     //current_mode: Box<dyn Mode>,
     state: State,
     last_timestamp: usize,
-    modes: [Box<dyn Mode>;2],
+    modes: Vec<Box<dyn Mode>>, //in the order of conf.modes
+    idle: IdleMode,
+    shaper: InputShaper,
+    exiting: bool, //EXIT was triggered, the program ends once the robot is seated
+    last_input: ControllerEvent, //after the input shaping, for the telemetry
+    consumed_buttons: Vec<Button>, //bound by the active mode itself, for the telemetry
+    stance: Stance, //what conf.stance is set to, it moves towards stance_target while in a mode
+    stance_target: Stance,
+    telemetry_sequence: u64,
+    time: f64, //seconds since the start, counted in ticks of conf.tick_period
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Copy, Clone)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms, clippy::large_enum_variant)]
 enum State {
     IN_MODE(usize),
-    CHANGING(usize, usize),
+    CHANGING(usize, usize), //the old mode returns to idle until it can blend over to the new one
+    BLENDING(Blend),
+    SETTLING(usize), //the controller was lost, the mode returns to idle
+    SETTLED(usize), //the mode is started again once the controller is back
+    SITTING_DOWN(usize), //the mode returns to idle before the robot sits down
+    SEATED(usize), //the mode is started again after standing up
+    STANDING_UP(usize),
 }
 
 impl InputHandler{
-    pub fn new() -> Self {
+    pub fn new(conf: &Config) -> Self {
+        Self::with_registry(&ModeRegistry::default(), conf)
+    }
+
+    //creates the modes listed in conf.modes, the registry can contain additional modes
+    pub fn with_registry(registry: &ModeRegistry, conf: &Config) -> Self {
+        let modes = registry.create_all(&conf.modes);
+        assert!(!modes.is_empty(), "none of the configured modes {:?} exists, available: {:?}", conf.modes, registry.names());
         Self {
-            state: State::IN_MODE(0),
+            state: State::SEATED(0), //stands up once X or a STAND command asks for it
             last_timestamp: 0,
-            //modes: [Box::new(WalkingEngine::new()), Box::new(GaitEngine::new()), Box::new(MoveBodyMode::new())]
-            modes: [Box::new(GaitEngine::new()), Box::new(MoveBodyMode::new())]
+            modes,
+            idle: IdleMode::new(),
+            shaper: InputShaper::new(),
+            exiting: false,
+            last_input: ControllerEvent::default(),
+            consumed_buttons: Vec::new(),
+            stance: conf.stance,
+            stance_target: conf.stance,
+            telemetry_sequence: 0,
+            time: 0.0,
         }
     }
 
@@ -54,34 +97,300 @@ impl InputHandler{
             input.clear_triggered();
         }
         self.last_timestamp = input.timestamp;
+        self.time += conf.tick_period;
+        let input = self.shaper.shape(&input, &conf.input_shaping, conf.tick_period);
+        self.last_input = input;
+        let actions = Actions::resolve(&input, &conf.button_mapping, self.mode_name());
+        self.consumed_buttons = actions::consumed_buttons(&conf.button_mapping, self.mode_name());
+
+        if actions.triggered(Action::EXIT) {
+            self.sit_down();
+            self.exiting = true;
+        }
+        self.update_stance(conf);
 
         match self.state {
             State::IN_MODE(current_mode) => {
-                if input.triggered(Button::ZR) {
-                    self.state = State::CHANGING(current_mode, (current_mode+1) % self.modes.len());
-                } else if input.triggered(Button::ZL) {
-                    self.state = State::CHANGING(current_mode, (current_mode as isize-1).rem_euclid(self.modes.len() as isize) as usize);
-                } else if input.triggered(Button::UP) {
+                if actions.triggered(Action::SIT_STAND) {
+                    self.handle_command(&Command::SIT, hexapod, conf);
+                } else if actions.triggered(Action::NEXT_MODE) {
+                    let next = (current_mode+1) % self.modes.len();
+                    self.handle_command(&Command::MODE(self.modes[next].name().to_string()), hexapod, conf);
+                } else if actions.triggered(Action::PREV_MODE) {
+                    let prev = (current_mode as isize-1).rem_euclid(self.modes.len() as isize) as usize;
+                    self.handle_command(&Command::MODE(self.modes[prev].name().to_string()), hexapod, conf);
+                } else if actions.triggered(Action::FASTER) {
                     conf.anim_timestep *= 1.2;
                     println!("{}", conf.anim_timestep);
-                } else if input.triggered(Button::DOWN) {
+                } else if actions.triggered(Action::SLOWER) {
                     conf.anim_timestep /= 1.2;
                     println!("{}", conf.anim_timestep);
+                } else if self.modes[current_mode].can_adjust_stance() {
+                    self.adjust_stance(&actions, conf);
                 }
 
-                self.modes[current_mode].handle_input(&input, hexapod, conf);
+                self.modes[current_mode].handle_input(&input, &actions, hexapod, conf);
             },
             State::CHANGING(from_mode, to_mode) => {
-                if self.modes[from_mode].return_to_idle(hexapod, conf) {
-                    self.state = State::IN_MODE(to_mode);
+                if self.modes[from_mode].can_blend() || self.modes[from_mode].return_to_idle(hexapod, conf) {
+                    self.modes[to_mode].enter(hexapod, conf);
+                    self.state = State::BLENDING(Blend::new(from_mode, to_mode, hexapod, conf));
+                }
+            },
+            State::BLENDING(mut blend) => {
+                //changing the mode again goes back to the old one from where the blend currently is
+                if actions.triggered(Action::NEXT_MODE) || actions.triggered(Action::PREV_MODE) {
+                    self.modes[blend.to].exit(hexapod, conf);
+                    self.modes[blend.from].enter(hexapod, conf);
+                    blend.reverse();
+                } else if actions.triggered(Action::SIT_STAND) {
+                    blend.sit_down = true;
+                }
+
+                hexapod.set_pose(&blend.to_pose);
+                self.modes[blend.to].handle_input(&input, &actions, hexapod, conf);
+                blend.to_pose = hexapod.get_pose();
+
+                let done = blend.advance(conf);
+                hexapod.set_pose(&blend.pose());
+                self.state = if !done {
+                    State::BLENDING(blend)
+                } else {
+                    self.modes[blend.from].exit(hexapod, conf);
+                    if blend.sit_down { State::SITTING_DOWN(blend.to) } else { State::IN_MODE(blend.to) }
+                };
+            },
+            State::SETTLING(mode) => {
+                self.state = State::IN_MODE(mode);
+                self.modes[mode].handle_input(&input, &actions, hexapod, conf);
+            },
+            State::SETTLED(mode) => {
+                self.modes[mode].enter(hexapod, conf);
+                self.state = State::IN_MODE(mode);
+            },
+            State::SITTING_DOWN(mode) => {
+                if self.modes[mode].return_to_idle(hexapod, conf) {
+                    self.modes[mode].exit(hexapod, conf);
+                    self.state = State::SEATED(mode);
+                }
+            },
+            State::SEATED(_) => {
+                if actions.triggered(Action::SIT_STAND) && !self.exiting {
+                    self.handle_command(&Command::STAND, hexapod, conf);
+                } else {
+                    self.idle.handle_input(&input, &actions, hexapod, conf);
+                }
+            },
+            State::STANDING_UP(mode) => {
+                if self.idle.return_to_idle(hexapod, conf) {
+                    self.modes[mode].enter(hexapod, conf);
+                    self.state = State::IN_MODE(mode);
+                }
+            },
+        }
+
+    }
+
+    //mode changes, standing and sitting are handled here, everything else by the active mode.
+    //Commands that arrive while the robot changes modes, sits or stands are dropped
+    pub fn handle_command(&mut self, command: &Command, hexapod: &mut Hexapod, conf: &Config) {
+        match command {
+            Command::SIT => self.sit_down(),
+            Command::STAND => self.stand_up(),
+            Command::MODE(name) => match (self.state, self.modes.iter().position(|m| m.name() == name)) {
+                (State::IN_MODE(current), Some(mode)) if current != mode => self.state = State::CHANGING(current, mode),
+                (_, None) => eprintln!("unknown mode: {}, available: {:?}", name, self.modes.iter().map(|m| m.name()).collect::<Vec<_>>()),
+                _ => {},
+            },
+            _ => {
+                if let State::IN_MODE(mode) = self.state {
+                    if !self.modes[mode].handle_command(command, hexapod, conf) {
+                        eprintln!("{} mode ignores {:?}", self.modes[mode].name(), command);
+                    }
+                }
+            },
+        }
+    }
+
+    //called instead of handle_input while the controller is lost. The active mode returns to its
+    //neutral pose and the robot stays standing, transitions that already started are finished
+    pub fn settle(&mut self, hexapod: &mut Hexapod, conf: &mut Config) {
+        self.state = match self.state {
+            State::IN_MODE(mode) | State::SETTLING(mode) => {
+                if self.modes[mode].return_to_idle(hexapod, conf) {
+                    self.modes[mode].exit(hexapod, conf);
+                    State::SETTLED(mode)
+                } else {
+                    State::SETTLING(mode)
                 }
-            }
+            },
+            State::SETTLED(mode) => State::SETTLED(mode),
+            _ => {
+                let event = ControllerEvent { timestamp: self.last_timestamp, ..Default::default() };
+                self.handle_input(event, hexapod, conf);
+                return;
+            },
+        };
+        self.time += conf.tick_period;
+    }
+
+    pub fn sit_down(&mut self) {
+        self.state = match self.state {
+            State::IN_MODE(mode) | State::CHANGING(mode, _) | State::SETTLING(mode) | State::SETTLED(mode) => State::SITTING_DOWN(mode),
+            State::BLENDING(mut blend) => {
+                blend.sit_down = true;
+                State::BLENDING(blend)
+            },
+            State::STANDING_UP(mode) => State::SEATED(mode),
+            other => other,
+        };
+    }
+
+    pub fn stand_up(&mut self) {
+        if let State::SEATED(mode) = self.state {
+            self.state = State::STANDING_UP(mode);
         }
+    }
 
+    //name of the mode that currently controls the robot, "idle" while sitting or standing up
+    pub fn mode_name(&self) -> &'static str {
+        match self.state {
+            State::IN_MODE(mode) | State::CHANGING(mode, _) | State::SETTLING(mode) | State::SETTLED(mode) | State::SITTING_DOWN(mode) => self.modes[mode].name(),
+            State::BLENDING(blend) => self.modes[blend.to].name(),
+            State::SEATED(_) | State::STANDING_UP(_) => self.idle.name(),
+        }
+    }
+
+    //the main loop fills in the link state and timing
+    pub fn get_telemetry(&mut self, hexapod: &mut Hexapod) -> TelemetryMessage {
+        self.telemetry_sequence += 1;
+        let gait = match self.state {
+            State::IN_MODE(mode) | State::CHANGING(mode, _) | State::SETTLING(mode) | State::SETTLED(mode) | State::SITTING_DOWN(mode) => self.modes[mode].gait_telemetry(),
+            State::BLENDING(blend) => self.modes[blend.to].gait_telemetry(),
+            State::SEATED(_) | State::STANDING_UP(_) => None,
+        };
+        TelemetryMessage {
+            sequence: self.telemetry_sequence,
+            timestamp: self.time,
+            mode: self.mode_name().to_string(),
+            gait,
+            input: self.last_input,
+            consumed_buttons: self.consumed_buttons.clone(),
+            ..hexapod.get_telemetry()
+        }
+    }
+
+    pub fn is_seated(&self) -> bool {
+        matches!(self.state, State::SEATED(_)) && self.idle.is_seated()
+    }
+
+    //true once the robot has sat down after EXIT, the servos can be released then
+    pub fn should_exit(&self) -> bool {
+        self.exiting && self.is_seated()
+    }
+
+    fn adjust_stance(&mut self, actions: &Actions, conf: &Config) {
+        let mut stance = self.stance_target;
+        if actions.triggered(Action::STANCE_HIGHER) {
+            stance.height += conf.stance_adjust_step;
+        } else if actions.triggered(Action::STANCE_LOWER) {
+            stance.height -= conf.stance_adjust_step;
+        } else if actions.triggered(Action::STANCE_WIDER) {
+            stance.width += conf.stance_adjust_step;
+        } else if actions.triggered(Action::STANCE_NARROWER) {
+            stance.width -= conf.stance_adjust_step;
+        } else {
+            return;
+        }
+
+        //a stance out of reach is ignored
+        if stance.is_reachable(conf.walking_step_height) {
+            self.stance_target = stance;
+        }
+    }
+
+    //moves conf.stance one animation step towards the target, but only while the active mode
+    //can follow it. The modes themselves, sitting down and standing up use conf.stance as it is
+    fn update_stance(&mut self, conf: &mut Config) {
+        //conf.stance was changed from outside, e.g. by a config patch
+        if conf.stance != self.stance {
+            self.stance_target = conf.stance;
+            conf.stance = self.stance;
+        }
+        let adjustable = match self.state {
+            State::IN_MODE(mode) => self.modes[mode].can_adjust_stance(),
+            _ => false,
+        };
+        if adjustable {
+            self.stance.step_towards(&self.stance_target, conf.walking_translating_resolution*0.5);
+            conf.stance = self.stance;
+        }
+    }
+}
+
+//the old mode is frozen at its last pose while the new one already runs, the robot moves
+//from one pose to the other over conf.mode_blend_time
+#[derive(Copy, Clone)]
+struct Blend {
+    from: usize,
+    to: usize,
+    from_pose: HexapodPose,
+    to_pose: HexapodPose, //the new mode's own pose, before blending
+    progress: f64,
+    sit_down: bool, //sits down once the blend has finished
+}
+
+impl Blend {
+    //the new mode starts from the neutral standing pose
+    fn new(from: usize, to: usize, hexapod: &Hexapod, conf: &Config) -> Self {
+        let from_pose = hexapod.get_pose();
+        let origin = from_pose.origin;
+        let to_pose = HexapodPose {
+            origin,
+            bodyTransform: BodyPose::neutral().to_isometry(conf.stance.height),
+            feet: Foot::all().map(|f| origin.transform_point3(conf.stance.foot_pos(f))),
+        };
+        Self { from, to, from_pose, to_pose, progress: 0.0, sit_down: false }
+    }
+
+    //returns true once the blend has finished
+    fn advance(&mut self, conf: &Config) -> bool {
+        self.progress = if conf.mode_blend_time > 0.0 { self.progress + conf.tick_period / conf.mode_blend_time } else { 1.0 };
+        //the sum of the steps can miss 1 by a rounding error
+        self.progress >= 1.0 - 1e-9
+    }
+
+    fn pose(&self) -> HexapodPose {
+        self.from_pose.lerp(&self.to_pose, smoothstep(self.progress))
+    }
+
+    //smoothstep is symmetric, so the blended pose stays the same. The InputHandler exits the mode
+    //that was blended to and enters the other one again
+    fn reverse(&mut self) {
+        std::mem::swap(&mut self.from, &mut self.to);
+        std::mem::swap(&mut self.from_pose, &mut self.to_pose);
+        self.progress = 1.0 - self.progress.min(1.0);
+    }
+}
+
+impl Button {
+    pub fn all() -> [Button; 12] {
+        use Button::*;
+        [A, B, X, Y, L, R, ZL, ZR, UP, DOWN, LEFT, RIGHT]
     }
 }
 
 impl ControllerEvent{
+    //combines the events that arrived within one tick into the newest one, the buttons triggered
+    //in any of them stay triggered so no press is lost. None if there are no events
+    pub fn merge(events: &[ControllerEvent]) -> Option<ControllerEvent> {
+        let mut merged = *events.last()?;
+        for event in events {
+            merged.triggered.iter_mut().zip(event.triggered.iter()).for_each(|(m, t)| *m |= *t);
+        }
+        Some(merged)
+    }
+
     pub fn clear_triggered(&mut self) {
         self.triggered.iter_mut().for_each(|t| *t = false);
     }
@@ -93,4 +402,60 @@ impl ControllerEvent{
     pub fn triggered(&self, button: Button) -> bool {
         self.triggered[button as usize]
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_seated_until_stand() {
+        let mut conf = Config::default();
+        let mut hexapod = Hexapod::new();
+        let mut input_handler = InputHandler::new(&conf);
+        for _ in 0..100 {
+            input_handler.handle_input(ControllerEvent::default(), &mut hexapod, &mut conf);
+        }
+        assert!(input_handler.is_seated());
+
+        input_handler.handle_command(&Command::STAND, &mut hexapod, &conf);
+        input_handler.handle_input(ControllerEvent::default(), &mut hexapod, &mut conf);
+        assert!(!input_handler.is_seated());
+    }
+
+    #[test]
+    fn blends_over_mode_blend_time_and_reverses() {
+        let conf = Config::default();
+        let hexapod = Hexapod::new(); //seated, so the blend moves every foot
+        let start = hexapod.get_pose();
+        let ticks = (conf.mode_blend_time / conf.tick_period).round() as usize;
+
+        let mut blend = Blend::new(0, 1, &hexapod, &conf);
+        let mut taken = 1;
+        while !blend.advance(&conf) {
+            taken += 1;
+        }
+        assert_eq!(taken, ticks);
+
+        let mut blend = Blend::new(0, 1, &hexapod, &conf);
+        for _ in 0..ticks / 2 {
+            assert!(!blend.advance(&conf));
+        }
+        let halfway = blend.pose();
+        blend.reverse();
+        assert_eq!((blend.from, blend.to), (1, 0));
+        assert!(blend.pose().feet.iter().zip(halfway.feet.iter()).all(|(a, b)| (*a - *b).length() < 1e-9));
+        for _ in 1..ticks - ticks / 2 {
+            assert!(!blend.advance(&conf));
+        }
+        assert!(blend.advance(&conf));
+        assert!(blend.pose().feet.iter().zip(start.feet.iter()).all(|(a, b)| (*a - *b).length() < 1e-9));
+    }
+
+    #[test]
+    fn blend_time_of_zero_switches_instantly() {
+        let conf = Config { mode_blend_time: 0.0, ..Config::default() };
+        let mut blend = Blend::new(0, 1, &Hexapod::new(), &conf);
+        assert!(blend.advance(&conf));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::config::{AxisShaping, InputShaping};
+use crate::input_handler::ControllerEvent;
+use crate::Vector2;
+
+//turns the raw stick and trigger values into the ones the modes use. The dead zone of a stick is
+//radial, so diagonals behave like the axes, and the remaining range is scaled to start at 0
+//instead of jumping to the dead zone value. The expo curve and rate limit are applied per axis
+pub struct InputShaper {
+    last: [f64; 6], //lx, ly, rx, ry, lt, rt after shaping
+}
+
+impl InputShaper {
+    pub fn new() -> Self {
+        Self {
+            last: [0.0; 6],
+        }
+    }
+
+    //called once per control tick, dt is the tick period the rate limit is applied over
+    pub fn shape(&mut self, event: &ControllerEvent, conf: &InputShaping, dt: f64) -> ControllerEvent {
+        let left = radial_dead_zone(Vector2::new(event.lx, event.ly), &conf.lx, &conf.ly);
+        let right = radial_dead_zone(Vector2::new(event.rx, event.ry), &conf.rx, &conf.ry);
+        let lt = scaled_dead_zone(event.lt, conf.lt.dead_zone);
+        let rt = scaled_dead_zone(event.rt, conf.rt.dead_zone);
+
+        let values = [left.x, left.y, right.x, right.y, lt, rt];
+        let axes = [&conf.lx, &conf.ly, &conf.rx, &conf.ry, &conf.lt, &conf.rt];
+        for ((last, value), axis) in self.last.iter_mut().zip(values).zip(axes) {
+            let mut target = expo(value, axis.expo);
+            if axis.invert {
+                target = -target;
+            }
+            *last = if axis.max_rate > 0.0 {
+                *last + (target - *last).clamp(-axis.max_rate * dt, axis.max_rate * dt)
+            } else {
+                target
+            };
+        }
+
+        let [lx, ly, rx, ry, lt, rt] = self.last;
+        ControllerEvent { lx, ly, rx, ry, lt, rt, ..*event }
+    }
+}
+
+impl Default for InputShaper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//the dead zone is an ellipse with the dead zones of both axes as radii
+fn radial_dead_zone(stick: Vector2, x: &AxisShaping, y: &AxisShaping) -> Vector2 {
+    let magnitude = stick.length();
+    if magnitude == 0.0 {
+        return stick;
+    }
+    let direction = stick / magnitude;
+    let inv_radius = |component: f64, dead_zone: f64| {
+        if component == 0.0 { 0.0 } else { (component / dead_zone).powi(2) }
+    };
+    let dead_zone = 1.0 / (inv_radius(direction.x, x.dead_zone) + inv_radius(direction.y, y.dead_zone)).sqrt();
+    direction * scaled_dead_zone(magnitude.min(1.0), dead_zone)
+}
+
+fn scaled_dead_zone(value: f64, dead_zone: f64) -> f64 {
+    if value.abs() <= dead_zone || dead_zone >= 1.0 {
+        0.0
+    } else {
+        value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone)
+    }
+}
+
+//blends between the linear and the cubic curve, both map -1, 0 and 1 onto themselves
+fn expo(value: f64, expo: f64) -> f64 {
+    let expo = expo.clamp(0.0, 1.0);
+    (1.0 - expo) * value + expo * value.powi(3)
+}
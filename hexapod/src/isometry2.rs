@@ -1,8 +1,5 @@
 use crate::{Rotation2, Vector2, Transform2, Point2, Point3, Vector3};
-use euclid::Transform2D;
 use crate::utils::clamp_abs;
-use std::cell::RefCell;
-use std::borrow::BorrowMut;
 use euclid::approxeq::ApproxEq;
 use crate::config::Config;
 
@@ -69,11 +66,6 @@ impl Isometry2 {
         self.rotation.to_transform().then(&self.translation.to_transform())
     }
 
-    #[inline]
-    fn get_inverse_transform(&self) -> Transform2 {
-        self.rotation.inverse().to_transform().then(&self.translation.to_transform().inverse().unwrap())
-    }
-
     pub fn transform_point(&self, point: Point2) -> Point2{
         self.rotation.transform_point(point) + self.translation
     }
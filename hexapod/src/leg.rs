@@ -1,5 +1,5 @@
-use crate::{Point3, Rotation2, Vector2, Vector3, Isometry2, Transform3, Angle};
-use crate::config::{HIP_LENGTH, UPPER_LEG_LENGTH, LOWER_LEG_LENGTH};
+use crate::{Point3, Rotation2, Vector2, Transform3, Angle};
+use crate::config::{HIP_LENGTH, UPPER_LEG_LENGTH, LOWER_LEG_LENGTH, SERVO_LIMITS};
 use crate::telemetry::LegTelemetry;
 use std::f64::consts::PI;
 
@@ -70,6 +70,17 @@ impl Leg{
         Some([alpha, beta, gamma])
     }
 
+    //checks if the foot position (relative to the leg origin) can be reached within the servo limits
+    pub fn can_reach(foot_pos: Point3, orientation: Vector2) -> bool {
+        let mut leg = Leg::new();
+        leg.set_rel_foot_pos(foot_pos);
+        match leg.get_angles(orientation) {
+            Some(angles) => angles.iter().zip(SERVO_LIMITS.iter())
+                .all(|(angle, (min, max))| (*min..=*max).contains(&angle.signed().radians)),
+            None => false,
+        }
+    }
+
     pub fn get_telemetry(&self, joint: Vector2, body_to_word: &Transform3) -> LegTelemetry {
         let joint_3d = joint.to_3d();
         LegTelemetry {
@@ -11,6 +11,10 @@ pub type Rotation3 = euclid::Rotation3D<f64, UnknownUnit, UnknownUnit>;
 pub type Transform3 = euclid::Transform3D<f64, UnknownUnit, UnknownUnit>;
 pub type Angle = euclid::Angle<f64>;
 pub use isometry2::Isometry2;
+pub use modes::Mode;
+pub use modes::registry::ModeRegistry;
+pub use modes::gait::GaitType;
+pub use utils::from_name;
 //pub use isometry3::Isometry3;
 pub type Isometry3 = euclid::RigidTransform3D<f64, UnknownUnit, UnknownUnit>;
 
@@ -18,7 +22,12 @@ pub type Isometry3 = euclid::RigidTransform3D<f64, UnknownUnit, UnknownUnit>;
 pub mod hexapod;
 pub mod telemetry;
 pub mod input_handler;
+pub mod command;
+pub mod actions;
+pub mod input_shaping;
 pub mod config;
+pub mod body_pose;
+pub mod urdf;
 mod utils;
 mod leg;
 mod isometry2;
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use serde::Deserialize;
+use splines::{Interpolation, Key, Spline};
+use crate::Vector3;
+use crate::actions::{Action, Actions};
+use crate::body_pose::BodyPose;
+use crate::config::Config;
+use crate::hexapod::{Hexapod, Foot};
+use crate::input_handler::ControllerEvent;
+use crate::modes::Mode;
+use crate::telemetry::GaitTelemetry;
+use crate::modes::gait::{GaitEngine, WalkingInput};
+
+//an animation as it is stored in a json file. The times are in beats if a bpm is given, otherwise in seconds
+#[derive(Deserialize, Debug, Clone)]
+pub struct Animation {
+    pub bpm: Option<f64>,
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+    pub keyframes: Vec<Keyframe>,
+}
+
+//everything but the time is optional. A keyframe without a body, walk or foot entry is skipped by
+//that track, it is interpolated between the keyframes that have one. A body is always a whole
+//pose though, its missing fields are zero and not interpolated: {"z": -15} also levels the body
+#[derive(Deserialize, Debug, Clone)]
+pub struct Keyframe {
+    pub time: f64,
+    #[serde(default)]
+    pub interpolation: KeyInterpolation, //how to get from this keyframe to the next one
+    pub body: Option<BodyPose>,
+    #[serde(default)]
+    pub feet: HashMap<Foot, [f64; 3]>, //offsets from the standing positions, only used while not walking
+    pub walk: Option<[f64; 3]>, //x, y and rotation, scaled like the sticks
+}
+
+#[derive(Deserialize, Debug, Default, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum KeyInterpolation {
+    STEP,
+    #[default]
+    LINEAR,
+    COSINE,
+}
+
+impl KeyInterpolation {
+    fn to_spline(self) -> Interpolation<f64, f64> {
+        match self {
+            Self::STEP => Interpolation::Step(1.0),
+            Self::LINEAR => Interpolation::Linear,
+            Self::COSINE => Interpolation::Cosine,
+        }
+    }
+}
+
+impl Animation {
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.keyframes.iter().map(|k| k.time).fold(0.0, f64::max)
+    }
+}
+
+//one spline per animated value, None if no keyframe sets it
+struct Tracks {
+    body: Option<[Spline<f64, f64>; 6]>,
+    feet: [Option<[Spline<f64, f64>; 3]>; 6],
+    walk: Option<[Spline<f64, f64>; 3]>,
+}
+
+impl Tracks {
+    fn new(animation: &Animation) -> Self {
+        let keyframes = &animation.keyframes;
+        Self {
+            body: track(keyframes, |k| k.body.map(|b| [b.x, b.y, b.z, b.roll, b.pitch, b.yaw])),
+            feet: Foot::all().map(|foot| track(keyframes, |k| k.feet.get(&foot).copied())),
+            walk: track(keyframes, |k| k.walk),
+        }
+    }
+}
+
+fn track<const N: usize>(keyframes: &[Keyframe], value: impl Fn(&Keyframe) -> Option<[f64; N]>) -> Option<[Spline<f64, f64>; N]> {
+    let keys = keyframes.iter()
+        .filter_map(|k| value(k).map(|v| (k.time, v, k.interpolation.to_spline())))
+        .collect::<Vec<_>>();
+    if keys.is_empty() {
+        return None;
+    }
+    Some(std::array::from_fn(|i| {
+        Spline::from_vec(keys.iter().map(|(time, v, interpolation)| Key::new(*time, v[i], *interpolation)).collect())
+    }))
+}
+
+fn sample<const N: usize>(track: &Option<[Spline<f64, f64>; N]>, time: f64) -> [f64; N] {
+    match track {
+        Some(splines) => splines.each_ref().map(|s| s.clamped_sample(time).unwrap_or(0.0)),
+        None => [0.0; N],
+    }
+}
+
+//plays back the animation from conf.dance_file. A pauses and resumes, R toggles looping,
+//left/right change the speed and tapping Y sets the tempo
+pub struct DanceMode {
+    animation: Option<(Animation, Tracks)>,
+    time: f64,
+    playing: bool,
+    looping: bool,
+    speed: f64,
+    tempo: Option<f64>,
+    taps: Vec<f64>, //clock times of the last taps
+    clock: f64, //seconds in ticks of conf.tick_period, only runs while the mode is active
+    pose: BodyPose,
+    feet: [Vector3; 6],
+    gait: GaitEngine,
+}
+
+impl Mode for DanceMode {
+    fn new() -> Self {
+        Self {
+            animation: None,
+            time: 0.0,
+            playing: true,
+            looping: false,
+            speed: 1.0,
+            tempo: None,
+            taps: Vec::new(),
+            clock: 0.0,
+            pose: BodyPose::neutral(),
+            feet: [Vector3::zero(); 6],
+            gait: GaitEngine::new(),
+        }
+    }
+
+    fn name(&self) -> &'static str { "dance" }
+
+    fn gait_telemetry(&self) -> Option<GaitTelemetry> {
+        Some(self.gait.telemetry())
+    }
+
+    //the file is read again every time, so changes to the dance show up without restarting
+    fn enter(&mut self, _hexapod: &mut Hexapod, conf: &Config) {
+        self.load(conf);
+    }
+
+    fn handle_input(&mut self, _event: &ControllerEvent, actions: &Actions, hexapod: &mut Hexapod, conf: &Config) {
+        if actions.triggered(Action::PLAY_PAUSE) {
+            self.playing = !self.playing;
+        } else if actions.triggered(Action::TOGGLE_LOOP) {
+            self.looping = !self.looping;
+        } else if actions.triggered(Action::TAP_TEMPO) {
+            self.tap();
+        } else if actions.triggered(Action::DANCE_FASTER) {
+            self.speed *= 1.1;
+        } else if actions.triggered(Action::DANCE_SLOWER) {
+            self.speed /= 1.1;
+        }
+
+        self.advance(conf);
+        let (body, feet, walk) = match &self.animation {
+            Some((_, tracks)) => {
+                let body = sample(&tracks.body, self.time);
+                let feet = tracks.feet.each_ref().map(|f| Vector3::from(sample(f, self.time)));
+                let walk = if self.playing { sample(&tracks.walk, self.time) } else { [0.0; 3] };
+                (BodyPose::new(body[0], body[1], body[2], body[3], body[4], body[5]), feet, walk)
+            },
+            _ => (BodyPose::neutral(), [Vector3::zero(); 6], [0.0; 3]),
+        };
+        self.apply(&body, &feet, &WalkingInput::new(walk[0], walk[1], walk[2]), hexapod, conf);
+    }
+
+    fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        self.apply(&BodyPose::neutral(), &[Vector3::zero(); 6], &WalkingInput::new(0.0, 0.0, 0.0), hexapod, conf)
+    }
+
+    fn can_blend(&self) -> bool {
+        self.gait.is_idle() && self.feet.iter().all(|f| f.length() < 0.1)
+    }
+
+    //the keyframe foot offsets are relative to the stance positions
+    fn can_adjust_stance(&self) -> bool {
+        false
+    }
+
+    fn exit(&mut self, _hexapod: &mut Hexapod, _conf: &Config) {
+        self.time = 0.0;
+        self.playing = true;
+        self.pose = BodyPose::neutral();
+        self.feet = [Vector3::zero(); 6];
+    }
+}
+
+impl DanceMode {
+    fn load(&mut self, conf: &Config) {
+        self.animation = None;
+        match Animation::load(&conf.dance_file) {
+            Ok(animation) => {
+                self.looping = animation.looping;
+                self.tempo = conf.dance_bpm.or(animation.bpm);
+                let tracks = Tracks::new(&animation);
+                self.animation = Some((animation, tracks));
+            },
+            Err(e) => eprintln!("could not load dance {}: {}", conf.dance_file, e),
+        }
+    }
+
+    fn advance(&mut self, conf: &Config) {
+        let dt = conf.tick_period;
+        self.clock += dt;
+
+        let animation = match &self.animation {
+            Some((animation, _)) if self.playing => animation,
+            _ => return,
+        };
+        let beats_per_second = match (animation.bpm, self.tempo) {
+            (Some(_), Some(tempo)) => tempo / 60.0,
+            _ => 1.0,
+        };
+        self.time += dt * self.speed * beats_per_second;
+
+        let duration = animation.duration();
+        if self.time > duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time = 0.0;
+                self.playing = false;
+            }
+        }
+    }
+
+    //the tempo is the average interval between the last taps
+    fn tap(&mut self) {
+        let now = self.clock;
+        self.taps.retain(|t| now - t < 3.0);
+        self.taps.push(now);
+        if self.taps.len() >= 2 {
+            let span = now - self.taps[0];
+            self.tempo = Some(60.0 * (self.taps.len() - 1) as f64 / span);
+        }
+    }
+
+    //returns true once the body and feet have reached the given pose and the robot stopped walking
+    fn apply(&mut self, body: &BodyPose, feet: &[Vector3; 6], walk: &WalkingInput, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        if walk.is_significant() || !self.gait.is_idle() {
+            self.gait.walk(walk, hexapod, conf);
+            self.feet = [Vector3::zero(); 6];
+        }
+
+        let target = body.clamp_reachable(hexapod, conf.stance.height);
+        let mut reached = self.pose.step_towards(&target, conf);
+        hexapod.bodyTransform = self.pose.to_isometry(conf.stance.height);
+
+        //the feet are controlled by the gait while walking
+        if self.gait.is_idle() {
+            for foot in Foot::all() {
+                let difference = feet[foot.id()] - self.feet[foot.id()];
+                self.feet[foot.id()] += difference.with_max_length(conf.walking_translating_resolution*0.5);
+                let mut pos = conf.stance.foot_pos(foot) + self.feet[foot.id()];
+                pos.z = pos.z.max(0.0);
+                hexapod.set_abs_foot_pos(foot, hexapod.origin.transform_point3(pos));
+                reached &= difference.length() < 0.1;
+            }
+        }
+        reached && self.gait.is_idle()
+    }
+}
@@ -1,21 +1,28 @@
-use crate::{Isometry2, Vector3, Vector2, Point2, Translation2, Angle, Rotation2};
-use std::time::Instant;
-use splines::{Interpolation, Key, Spline};
-use crate::config::{INPUT_MIN_MAG, INPUT_FINALIZED_DELAY, Config};
+use crate::{Isometry2, Vector3, Vector2, Rotation2};
+use crate::actions::{Action, Actions};
+use crate::body_pose::BodyPose;
+use crate::command::Command;
+use crate::config::{INPUT_MIN_MAG, INPUT_SIMILAR_MAG, INPUT_FINALIZED_DELAY, Config};
 use crate::hexapod::{Hexapod, Foot};
-use crate::input_handler::{Button, ControllerEvent};
+use crate::input_handler::ControllerEvent;
 use crate::modes::Mode;
-use crate::utils::clamp_abs;
+use crate::telemetry::{FootContact, GaitTelemetry};
+use serde::{Serialize, Deserialize};
 
 pub struct GaitEngine{
     state: State,
     gait: GaitType,
     last_input: WalkingInput,
-    last_input_change: Instant,
+    input_unchanged: f64, //seconds since the input last changed
     input_finalized: bool,
+    body_pose: BodyPose, //body offset that is held while walking
+    target_pose: BodyPose,
+    velocity: WalkingInput, //set by the WALK command
+    stick_input: WalkingInput, //the last walking input from the sticks, only changes are sent as commands
 }
 
 #[derive(Copy, Clone)]
+#[allow(clippy::upper_case_acronyms, clippy::large_enum_variant)]
 enum State {
     IDLE,
     STEP(WalkingState),
@@ -41,14 +48,14 @@ impl WalkingState {
         }
     }
 
-    pub fn do_step(mut self, input: &WalkingInput, mut hexapod: &mut Hexapod, conf: &Config) -> State {
+    pub fn do_step(mut self, input: &WalkingInput, hexapod: &mut Hexapod, conf: &Config) -> State {
         for foot in Foot::all() {
             let (progress, height) = self.gait.get_foot_state(foot, self.step_progress, conf);
 
-            let should_step = (progress > 0. && progress < 1.);
+            let should_step = progress > 0. && progress < 1.;
 
             self.feet[foot.id()] = match self.feet[foot.id()] {
-                FootState::STEPPING(from, to, is_idle) if !should_step => {
+                FootState::STEPPING(_, to, is_idle) if !should_step => {
                     FootState::STANDING(to, is_idle)
                 },
                 FootState::STANDING(pos, _) if should_step => {
@@ -60,7 +67,7 @@ impl WalkingState {
 
                         self.fixed_center = desired;
                         FootState::STEPPING(pos, desired, false)
-                    } else if pos.approx_eq(&self.fixed_center) {
+                    } else if pos.approx_eq(&self.fixed_center) && is_in_stance(foot, &pos, hexapod, conf) {
                         FootState::STANDING(pos, true)
                     } else {
                         FootState::STEPPING(pos, self.fixed_center, true)
@@ -72,7 +79,7 @@ impl WalkingState {
             self.centers[foot.id()] = self.feet[foot.id()].current_pos(progress);
 
             if let FootState::STEPPING(_,_,_) = self.feet[foot.id()] {
-                let mut new_pos = self.centers[foot.id()].transform_point3(foot.initial_foot_pos());
+                let mut new_pos = self.centers[foot.id()].transform_point3(conf.stance.foot_pos(foot));
                 new_pos.z = height * conf.walking_step_height;
                 hexapod.set_abs_foot_pos(foot, new_pos);
             }
@@ -99,88 +106,169 @@ impl Mode for GaitEngine{
             state: State::IDLE,
             gait: GaitType::default(),
             last_input: WalkingInput::new(0.0, 0.0, 0.0),
-            last_input_change: Instant::now(),
+            input_unchanged: 0.0,
             input_finalized: false,
+            body_pose: BodyPose::neutral(),
+            target_pose: BodyPose::neutral(),
+            velocity: WalkingInput::new(0.0, 0.0, 0.0),
+            stick_input: WalkingInput::new(0.0, 0.0, 0.0),
         }
     }
 
-    fn handle_input(&mut self, input: &ControllerEvent, hexapod: &mut Hexapod, conf: &Config) {
-        self.next_step(input, hexapod, conf);
+    fn name(&self) -> &'static str { "gait" }
+
+    fn handle_input(&mut self, input: &ControllerEvent, actions: &Actions, hexapod: &mut Hexapod, conf: &Config) {
+        for command in self.translate(input, actions, conf) {
+            self.handle_command(&command, hexapod, conf);
+        }
+        self.next_step(hexapod, conf);
+    }
+
+    fn handle_command(&mut self, command: &Command, _hexapod: &mut Hexapod, _conf: &Config) -> bool {
+        match command {
+            Command::WALK { vx, vy, omega } => {
+                self.velocity = WalkingInput::new(vx.clamp(-1.0, 1.0), vy.clamp(-1.0, 1.0), -omega.clamp(-1.0, 1.0));
+            },
+            Command::BODY_POSE(pose) => self.target_pose = *pose,
+            //the new gait is used from the next time the robot starts walking
+            Command::GAIT(gait) => self.gait = *gait,
+            Command::STOP => {
+                self.velocity = WalkingInput::new(0.0, 0.0, 0.0);
+                self.target_pose = BodyPose::neutral();
+            },
+            _ => return false,
+        }
+        true
     }
 
     fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
-        self.next_step(&ControllerEvent::default(), hexapod, conf);
-        matches!(self.state, State::IDLE)
+        self.handle_command(&Command::STOP, hexapod, conf);
+        self.next_step(hexapod, conf);
+        matches!(self.state, State::IDLE) && self.body_pose.approx_eq(&self.target_pose)
+    }
+
+    fn can_blend(&self) -> bool {
+        self.is_idle()
+    }
+
+    fn gait_telemetry(&self) -> Option<GaitTelemetry> {
+        Some(self.telemetry())
+    }
+
+    fn exit(&mut self, _hexapod: &mut Hexapod, _conf: &Config) {
+        self.body_pose = BodyPose::neutral();
+        self.target_pose = BodyPose::neutral();
+        self.velocity = WalkingInput::new(0.0, 0.0, 0.0);
+        self.stick_input = WalkingInput::new(0.0, 0.0, 0.0);
     }
 }
 
 impl GaitEngine {
-    pub fn next_step(&mut self, event: &ControllerEvent, mut hexapod: &mut Hexapod, conf: &Config){
-        let input = WalkingInput::from(event);
-        self.handle_input(&input);
+    //moves the body towards the target pose and walks with the commanded velocity
+    pub fn next_step(&mut self, hexapod: &mut Hexapod, conf: &Config){
+        self.body_pose.step_towards(&self.target_pose, conf);
+        hexapod.bodyTransform = self.body_pose.to_isometry(conf.stance.height);
+
+        let velocity = self.velocity.clone();
+        self.walk(&velocity, hexapod, conf);
+    }
+
+    //advances the gait without touching the body pose, so other modes can walk as well
+    pub fn walk(&mut self, input: &WalkingInput, hexapod: &mut Hexapod, conf: &Config) {
+        self.handle_input(input, conf);
 
         self.state = match self.state {
             State::IDLE => {
-                if event.triggered(Button::LEFT) {
-                    self.gait = self.gait.prev();
-                } else if event.triggered(Button::RIGHT) {
-                    self.gait = self.gait.next();
-                }
-
-                if input.is_significant() {
+                //the feet also have to step when the stance width has changed while standing
+                let origin = hexapod.origin;
+                if input.is_significant() || !Foot::all().iter().all(|f| is_in_stance(*f, &origin, hexapod, conf)) {
                     State::STEP(WalkingState::new(&self.gait, hexapod.origin))
                 } else {
                     State::IDLE
                 }
             },
             State::STEP(state) => {
-                state.do_step(&input, hexapod, conf)
+                state.do_step(input, hexapod, conf)
             }
         }
     }
 
-    fn reset_input_finalized(&mut self) {
-        self.input_finalized = false;
-        self.last_input_change = Instant::now();
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::IDLE)
     }
 
-    //TODO: move to own struct
-    fn handle_input(&mut self, input: &WalkingInput){
-        if self.last_input.is_similar_to(input) == false {
-            self.last_input_change = Instant::now();
-        }
-        self.last_input = input.clone();
-        self.input_finalized = self.last_input_change.elapsed().as_millis() > INPUT_FINALIZED_DELAY;
+    pub fn telemetry(&self) -> GaitTelemetry {
+        let (step_progress, feet) = match &self.state {
+            State::IDLE => (0.0, [FootContact::STANDING; 6]),
+            State::STEP(state) => (state.step_progress, state.feet.map(|f| match f {
+                FootState::STANDING(_, _) => FootContact::STANDING,
+                FootState::STEPPING(_, _, _) => FootContact::STEPPING,
+            })),
+        };
+        GaitTelemetry { gait: self.gait, step_progress, feet }
     }
-}
 
-//TODO: think of a better name
-#[derive(Copy, Clone)]
-struct DirectionEngine {
-    pos: Isometry2,
-    idle_steps: usize,
-}
+    //the sticks walk unless SHIFT_BODY or TURN_BODY is held. SHIFT_BODY shifts and tilts the body,
+    //TURN_BODY sets its height and yaw. The pose is kept after releasing the button, so the robot can
+    //walk with it until RESET_POSE. The gait can only be changed while standing.
+    //Only changes are sent, so commands from other sources stay in effect while the sticks rest
+    fn translate(&mut self, event: &ControllerEvent, actions: &Actions, conf: &Config) -> Vec<Command> {
+        let mut commands = Vec::new();
+
+        let posing = actions.held(Action::SHIFT_BODY) || actions.held(Action::TURN_BODY);
+        let input = if posing { WalkingInput::new(0.0, 0.0, 0.0) } else { WalkingInput::from(event) };
+        if input != self.stick_input {
+            commands.push(Command::walk(&input));
+            self.stick_input = input;
+        }
 
-impl DirectionEngine {
-    pub fn get_next_pos(&mut self, input: &WalkingInput, foot: Foot, gait: &GaitInfo, conf: &Config) -> Isometry2{
-        if !input.is_significant() {
-            self.idle_steps += 1;
-            return self.pos;
-        } else {
-            self.idle_steps = 0;
+        let range = &conf.body_pose_range;
+        let mut target = self.target_pose;
+        if actions.held(Action::SHIFT_BODY) {
+            target.x = event.lx * range.x;
+            target.y = event.ly * range.y;
+            target.roll = event.rx * range.roll;
+            target.pitch = -event.ry * range.pitch;
+        } else if actions.held(Action::TURN_BODY) {
+            target.yaw = -event.lx * range.yaw;
+            target.z = event.ly * range.z;
+        } else if actions.triggered(Action::RESET_POSE) {
+            target = BodyPose::neutral();
+        }
+        if target != self.target_pose {
+            commands.push(Command::BODY_POSE(target));
         }
 
-        let translation = self.pos.rotation().transform_vector(input.translation());
-        let desired_pos = translation * 2. * conf.walking_max_step_dist * gait.step_weight[foot.id()] + self.pos.translation();
-        let desired_rot = (conf.walking_max_rotation_dist * input.rot()) * gait.step_weight[foot.id()] + self.pos.rotation().get_angle();
-        let next_pos = Isometry2::new(desired_pos, Rotation2::new(desired_rot));
+        if self.is_idle() {
+            if actions.triggered(Action::PREV_GAIT) {
+                commands.push(Command::GAIT(self.gait.prev()));
+            } else if actions.triggered(Action::NEXT_GAIT) {
+                commands.push(Command::GAIT(self.gait.next()));
+            }
+        }
+        commands
+    }
 
-        self.pos = next_pos;
-        next_pos
+    //TODO: move to own struct
+    fn handle_input(&mut self, input: &WalkingInput, conf: &Config){
+        if self.last_input.is_similar_to(input) {
+            self.input_unchanged += conf.tick_period;
+        } else {
+            self.input_unchanged = 0.0;
+        }
+        self.last_input = input.clone();
+        self.input_finalized = self.input_unchanged > INPUT_FINALIZED_DELAY;
     }
 }
 
+//true if the foot stands where the current stance expects it relative to the center
+fn is_in_stance(foot: Foot, center: &Isometry2, hexapod: &Hexapod, conf: &Config) -> bool {
+    let expected = center.transform_point3(conf.stance.foot_pos(foot));
+    (hexapod.get_abs_foot_pos(foot) - expected).length() < 1.0
+}
+
 #[derive(Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 enum FootState {
     STANDING(Isometry2, bool),
     STEPPING(Isometry2, Isometry2, bool),
@@ -193,14 +281,16 @@ impl FootState {
 
     pub fn current_pos(&self, progress: f64) -> Isometry2 {
         match self {
-            Self::STEPPING(from, to, _) => Isometry2::lerp(&from, &to, progress),
+            Self::STEPPING(from, to, _) => Isometry2::lerp(from, to, progress),
             Self::STANDING(pos, _) => *pos
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-enum GaitType {
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum GaitType {
+    #[default]
     TRIPOD,
     DELAYED_TRIPOD,
     RIPPLE,
@@ -247,12 +337,6 @@ impl GaitType {
     }
 }
 
-impl Default for GaitType{
-    fn default() -> Self {
-        Self::TRIPOD
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 struct GaitInfo {
     step_start: [f64; 6], //stores the time each foot begins its step
@@ -282,21 +366,19 @@ impl GaitInfo {
 }
 
 //TODO: combine with identical struct in walking.rs
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct WalkingInput {
     inner: Vector3
 }
 
 impl WalkingInput {
-    pub fn new(mut x: f64, mut y: f64, mut rot: f64) -> Self{
-        if x.abs() < INPUT_MIN_MAG { x = 0.0 }
-        if y.abs() < INPUT_MIN_MAG { y = 0.0 }
-        if rot.abs() < INPUT_MIN_MAG { rot = 0.0 }
+    //the dead zone is part of the input shaping, so small values are kept for slow walking
+    pub fn new(x: f64, y: f64, rot: f64) -> Self{
         Self{ inner: Vector3::new(x, y, -rot) }
     }
 
     pub fn is_similar_to(&self, other: &WalkingInput) -> bool{
-        (self.inner - other.inner).length() < INPUT_MIN_MAG
+        (self.inner - other.inner).length() < INPUT_SIMILAR_MAG
     }
 
     pub fn is_significant(&self) -> bool{
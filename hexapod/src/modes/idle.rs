@@ -1,26 +1,194 @@
-use crate::config::{BODY_DIST_TO_GROUND, Config};
-use crate::hexapod::Hexapod;
+use std::collections::VecDeque;
+use crate::Point3;
+use crate::actions::Actions;
+use crate::config::{Config, Stance, SEATED_FOOT_HEIGHT};
+use crate::hexapod::{Hexapod, Foot};
 use crate::input_handler::ControllerEvent;
 use crate::modes::Mode;
 use crate::utils::clamp_abs;
 
+//ride heights and stance widths the robot passes through between sitting and standing. While
+//standing up the feet step to the new width first and then the body moves to the new height,
+//sitting down does the same in reverse
+const STAND_UP_WAYPOINTS: [Stance; 2] = [
+    Stance { height: 40.0, width: 70.0 },
+    Stance { height: 70.0, width: 50.0 },
+];
+
 #[derive(Debug, Copy, Clone)]
-pub struct IdleMode{}
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+enum Action {
+    STEP([Foot; 3], f64), //lifts the feet and puts them down at the given stance width
+    MOVE_FEET(f64), //moves all feet straight up or down to the given height above the ground
+    MOVE_BODY(f64),
+}
+
+//sits down with the body on the ground (handle_input) and stands back up (return_to_idle)
+#[derive(Debug, Clone)]
+pub struct IdleMode{
+    sitting: bool, //direction of the current or last sequence
+    actions: VecDeque<Action>,
+    progress: f64,
+    from: Option<[Point3; 6]>, //foot positions (relative to the origin) when the current action started
+}
 
 impl Mode for IdleMode {
-    fn new() -> Self { Self{} }
+    fn new() -> Self {
+        Self{
+            sitting: true,
+            actions: VecDeque::new(),
+            progress: 0.0,
+            from: None,
+        }
+    }
+
+    fn name(&self) -> &'static str { "idle" }
 
-    fn handle_input(&mut self, _: &ControllerEvent, hexapod: &mut Hexapod, conf: &Config){
-        move_body_to_height(hexapod, 0.0, conf);
+    fn handle_input(&mut self, _: &ControllerEvent, _: &Actions, hexapod: &mut Hexapod, conf: &Config){
+        if !self.sitting && self.actions.is_empty() {
+            self.sitting = true;
+            self.actions = sit_down_sequence(conf);
+        }
+        self.run(hexapod, conf);
     }
 
     fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
-        move_body_to_height(hexapod, BODY_DIST_TO_GROUND, conf)
+        if self.sitting && self.actions.is_empty() {
+            self.sitting = false;
+            self.actions = stand_up_sequence(conf);
+        }
+        self.run(hexapod, conf);
+        !self.sitting && self.actions.is_empty()
     }
 }
 
+impl IdleMode {
+    pub fn is_seated(&self) -> bool {
+        self.sitting && self.actions.is_empty()
+    }
+
+    fn run(&mut self, hexapod: &mut Hexapod, conf: &Config) {
+        let action = match self.actions.front() {
+            Some(action) => *action,
+            None => return,
+        };
+        let origin = hexapod.origin;
+        let from = *self.from.get_or_insert_with(|| {
+            Foot::all().map(|f| origin.inv_transform_point3(hexapod.get_abs_foot_pos(f)))
+        });
+
+        let done = match action {
+            Action::STEP(feet, width) => {
+                self.progress = (self.progress + conf.anim_timestep).min(1.0);
+                let height = conf.foot_height.clamped_sample(self.progress).unwrap() * conf.walking_step_height;
+                let stance = Stance { width, ..conf.stance };
+                for foot in feet {
+                    let mut pos = from[foot.id()].lerp(stance.foot_pos(foot), self.progress);
+                    pos.z = height;
+                    hexapod.set_abs_foot_pos(foot, origin.transform_point3(pos));
+                }
+                self.progress >= 1.0
+            },
+            Action::MOVE_FEET(height) => {
+                let mut done = true;
+                for foot in Foot::all() {
+                    let mut pos = hexapod.get_abs_foot_pos(foot);
+                    let delta = height - pos.z;
+                    pos.z += clamp_abs(delta, conf.walking_translating_resolution*0.5);
+                    hexapod.set_abs_foot_pos(foot, pos);
+                    done &= delta.abs() < 0.1;
+                }
+                done
+            },
+            Action::MOVE_BODY(height) => move_body_to_height(hexapod, height, conf),
+        };
+
+        if done {
+            self.actions.pop_front();
+            self.progress = 0.0;
+            self.from = None;
+        }
+    }
+}
+
+fn stand_up_sequence(conf: &Config) -> VecDeque<Action> {
+    let mut actions = VecDeque::from(vec![Action::MOVE_FEET(0.0)]);
+    let mut width = Stance::seated().width;
+    for stance in waypoints(conf).iter().skip(1) {
+        if stance.width != width {
+            actions.push_back(Action::STEP(Foot::even(), stance.width));
+            actions.push_back(Action::STEP(Foot::odd(), stance.width));
+            width = stance.width;
+        }
+        actions.push_back(Action::MOVE_BODY(stance.height));
+    }
+    actions
+}
+
+fn sit_down_sequence(conf: &Config) -> VecDeque<Action> {
+    let mut actions = VecDeque::new();
+    let mut width = conf.stance.width;
+    for stance in waypoints(conf).iter().rev().skip(1) {
+        actions.push_back(Action::MOVE_BODY(stance.height));
+        if stance.width != width {
+            actions.push_back(Action::STEP(Foot::even(), stance.width));
+            actions.push_back(Action::STEP(Foot::odd(), stance.width));
+            width = stance.width;
+        }
+    }
+    actions.push_back(Action::MOVE_FEET(SEATED_FOOT_HEIGHT));
+    actions
+}
+
+//after the fixed waypoints the robot continues to the configured stance,
+//going through the default stance if there is no direct way
+fn waypoints(conf: &Config) -> Vec<Stance> {
+    let mut waypoints = vec![Stance::seated()];
+    waypoints.extend_from_slice(&STAND_UP_WAYPOINTS);
+    let last = STAND_UP_WAYPOINTS[STAND_UP_WAYPOINTS.len() - 1];
+    if let Some(stance) = intermediate(&last, &conf.stance, conf) {
+        waypoints.push(stance);
+    } else {
+        let default = Stance::default();
+        waypoints.extend(intermediate(&last, &default, conf));
+        waypoints.push(default);
+        waypoints.extend(intermediate(&default, &conf.stance, conf));
+    }
+    waypoints.push(conf.stance);
+    waypoints
+}
+
+//either the feet step to the new width first or the body moves to the new height first,
+//whichever keeps the feet reachable
+fn intermediate(from: &Stance, to: &Stance, conf: &Config) -> Option<Stance> {
+    let width_first = Stance { height: from.height, width: to.width };
+    let height_first = Stance { height: to.height, width: from.width };
+    [width_first, height_first].iter().copied().find(|s| s.is_reachable(conf.walking_step_height))
+}
+
 fn move_body_to_height(hexapod: &mut Hexapod, height: f64, conf: &Config) -> bool {
     let delta = height - hexapod.bodyTransform.translation.z;
-    hexapod.bodyTransform.translation.z += clamp_abs(delta, conf.walking_translating_resolution);
-    delta < conf.walking_translating_resolution*0.1
-}
\ No newline at end of file
+    hexapod.bodyTransform.translation.z += clamp_abs(delta, conf.walking_translating_resolution*0.5);
+    delta.abs() < conf.walking_translating_resolution*0.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sits_down_where_the_robot_boots() {
+        let conf = Config::default();
+        let mut hexapod = Hexapod::new();
+        let mut idle = IdleMode::new();
+        assert!((0..1000).any(|_| idle.return_to_idle(&mut hexapod, &conf)));
+        assert!((0..1000).any(|_| {
+            idle.handle_input(&ControllerEvent::default(), &Actions::default(), &mut hexapod, &conf);
+            idle.is_seated()
+        }));
+        for foot in Foot::all() {
+            let pos = hexapod.origin.inv_transform_point3(hexapod.get_abs_foot_pos(foot));
+            assert!((pos - Stance::seated_foot_pos(foot)).length() < 0.1, "{:?} at {:?}", foot, pos);
+        }
+    }
+}
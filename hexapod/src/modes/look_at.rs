@@ -0,0 +1,137 @@
+use crate::{Point3, Vector3};
+use crate::actions::Actions;
+use crate::body_pose::BodyPose;
+use crate::command::Command;
+use crate::config::Config;
+use crate::hexapod::Hexapod;
+use crate::input_handler::ControllerEvent;
+use crate::modes::Mode;
+use crate::telemetry::GaitTelemetry;
+use crate::modes::gait::{GaitEngine, WalkingInput};
+use crate::utils::clamp_abs;
+
+//rotates the body so that its front points at a target in world coordinates. When the target
+//leaves the body pose range the robot turns in place (yaw) or backs away from it (pitch)
+pub struct LookAtMode {
+    target: Option<Point3>,
+    pose: BodyPose,
+    gait: GaitEngine,
+}
+
+impl Mode for LookAtMode {
+    fn new() -> Self {
+        Self {
+            target: None,
+            pose: BodyPose::neutral(),
+            gait: GaitEngine::new(),
+        }
+    }
+
+    fn name(&self) -> &'static str { "look_at" }
+
+    fn gait_telemetry(&self) -> Option<GaitTelemetry> {
+        Some(self.gait.telemetry())
+    }
+
+    //the left stick moves the target horizontally (relative to the robot), the right stick up and down
+    fn handle_input(&mut self, event: &ControllerEvent, _actions: &Actions, hexapod: &mut Hexapod, conf: &Config) {
+        let origin = hexapod.origin;
+        let target = self.target.get_or_insert_with(|| {
+            origin.transform_point3(Point3::new(0.0, conf.look_at_distance, conf.stance.height))
+        });
+        *target += origin.transform_vector3(Vector3::new(event.lx, event.ly, event.ry) * conf.look_at_target_speed);
+
+        let target = *target;
+        self.track(target, hexapod, conf);
+    }
+
+    fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        self.gait.walk(&WalkingInput::new(0.0, 0.0, 0.0), hexapod, conf);
+        let reached = self.move_towards(&BodyPose::neutral(), hexapod, conf);
+        reached && self.gait.is_idle()
+    }
+
+    fn can_blend(&self) -> bool {
+        self.gait.is_idle()
+    }
+
+    //the next time the mode is entered the target starts in front of the robot again
+    fn exit(&mut self, _hexapod: &mut Hexapod, _conf: &Config) {
+        self.target = None;
+        self.pose = BodyPose::neutral();
+    }
+
+    fn handle_command(&mut self, command: &Command, _hexapod: &mut Hexapod, _conf: &Config) -> bool {
+        match command {
+            Command::LOOK_AT(target) => self.target = Some(*target),
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl LookAtMode {
+    fn track(&mut self, target: Point3, hexapod: &mut Hexapod, conf: &Config) {
+        let center = hexapod.origin.transform_point3(Point3::new(0.0, 0.0, conf.stance.height));
+        let direction = hexapod.origin.inv_transform_vector3(target - center);
+        let yaw = (-direction.x).atan2(direction.y);
+        let pitch = direction.z.atan2(direction.xy().length());
+
+        let range = &conf.body_pose_range;
+        let turn = clamp_abs(excess(yaw, range.yaw) * conf.look_at_walk_gain, 1.0);
+        let back_off = clamp_abs(excess(pitch, range.pitch).abs() * conf.look_at_walk_gain, 1.0);
+        self.gait.walk(&WalkingInput::new(0.0, -back_off, -turn), hexapod, conf);
+
+        let desired = BodyPose {
+            yaw: clamp_abs(yaw, range.yaw),
+            pitch: clamp_abs(pitch, range.pitch),
+            ..BodyPose::neutral()
+        };
+        self.move_towards(&desired, hexapod, conf);
+    }
+
+    fn move_towards(&mut self, desired: &BodyPose, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        let target = desired.clamp_reachable(hexapod, conf.stance.height);
+        let reached = self.pose.step_towards(&target, conf);
+        hexapod.bodyTransform = self.pose.to_isometry(conf.stance.height);
+        reached
+    }
+}
+
+//how far the angle lies outside of [-max, max]
+fn excess(angle: f64, max: f64) -> f64 {
+    angle - clamp_abs(angle, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Point3;
+    use crate::command::Command;
+    use crate::config::Config;
+    use crate::hexapod::Hexapod;
+    use crate::input_handler::{ControllerEvent, InputHandler};
+
+    #[test]
+    fn look_at_command_turns_the_robot_towards_the_target() {
+        let mut conf = Config { modes: vec![String::from("look_at")], ..Config::default() };
+        let mut hexapod = Hexapod::new();
+        let mut input_handler = InputHandler::new(&conf);
+        input_handler.handle_command(&Command::STAND, &mut hexapod, &conf);
+        for _ in 0..1000 {
+            if input_handler.mode_name() == "look_at" {
+                break;
+            }
+            input_handler.handle_input(ControllerEvent::default(), &mut hexapod, &mut conf);
+        }
+        assert_eq!(input_handler.mode_name(), "look_at");
+
+        //far to the front left, outside of the body's yaw range
+        input_handler.handle_command(&Command::LOOK_AT(Point3::new(-300.0, 300.0, conf.stance.height)), &mut hexapod, &conf);
+        for _ in 0..200 {
+            input_handler.handle_input(ControllerEvent::default(), &mut hexapod, &mut conf);
+        }
+        let msg = input_handler.get_telemetry(&mut hexapod);
+        assert!(msg.rotation > 0.1, "the robot didn't turn left: {}", msg.rotation);
+        assert!(msg.body.rotation[2] > 0.0, "the body didn't yaw left: {:?}", msg.body.rotation);
+    }
+}
@@ -1,14 +1,45 @@
+use crate::actions::Actions;
+use crate::command::Command;
 use crate::config::Config;
 use crate::input_handler::ControllerEvent;
 use crate::hexapod::Hexapod;
+use crate::telemetry::GaitTelemetry;
 
 pub mod idle;
 //pub mod walking;
 pub mod gait;
 pub mod move_body;
+pub mod look_at;
+pub mod single_leg;
+pub mod dance;
+pub mod registry;
 
 pub trait Mode {
     fn new() -> Self where Self: Sized;
-    fn handle_input(&mut self, input: &ControllerEvent, hexapod: &mut Hexapod, config: &Config);
+    //used in conf.modes and reported in the telemetry. The bindings in conf.button_mapping with
+    //this name are the buttons the mode consumes (see actions::consumed_buttons)
+    fn name(&self) -> &'static str;
+    //the actions are resolved from the input with conf.button_mapping for this mode
+    fn handle_input(&mut self, input: &ControllerEvent, actions: &Actions, hexapod: &mut Hexapod, config: &Config);
     fn return_to_idle(&mut self, hexapod: &mut Hexapod, config: &Config) -> bool;
+
+    //only updates the targets of the mode, the robot moves towards them in the following handle_input calls.
+    //Returns false if the mode doesn't support the command
+    fn handle_command(&mut self, _command: &Command, _hexapod: &mut Hexapod, _config: &Config) -> bool { false }
+
+    //called before the first handle_input after the mode became active
+    fn enter(&mut self, _hexapod: &mut Hexapod, _config: &Config) {}
+    //called once another mode (or sitting down) has taken over, resets the mode to its neutral pose
+    fn exit(&mut self, _hexapod: &mut Hexapod, _config: &Config) {}
+
+    //false while the feet are not standing in their stance positions. Instead of blending over
+    //to the next mode directly, return_to_idle is then called until this returns true
+    fn can_blend(&self) -> bool { true }
+
+    //false if the mode places the feet relative to conf.stance, they would slide over the ground
+    //when it changes. The stance is then only adjusted once another mode is active
+    fn can_adjust_stance(&self) -> bool { true }
+
+    //state of the gait for the telemetry, None if the mode doesn't walk
+    fn gait_telemetry(&self) -> Option<GaitTelemetry> { None }
 }
@@ -1,36 +1,76 @@
-use euclid::Rotation3D;
-use crate::config::{BODY_DIST_TO_GROUND, Config};
+use crate::actions::{Action, Actions};
+use crate::body_pose::BodyPose;
+use crate::command::Command;
+use crate::config::Config;
 use crate::hexapod::Hexapod;
 use crate::input_handler::ControllerEvent;
 use crate::modes::Mode;
-use crate::{Angle, Vector2, Vector3};
+use crate::Vector2;
 
 #[derive(Debug, Copy, Clone)]
 pub struct MoveBodyMode{
-    current_rot: Vector2
+    pose: BodyPose,
+    target: BodyPose, //set by the BODY_POSE command
+    stick_pose: BodyPose, //the last pose from the sticks, only changes are sent as commands
 }
 
 impl Mode for MoveBodyMode {
     fn new() -> Self {
         Self{
-            current_rot: Vector2::zero()
+            pose: BodyPose::neutral(),
+            target: BodyPose::neutral(),
+            stick_pose: BodyPose::neutral(),
         }
     }
 
-    fn handle_input(&mut self, event: &ControllerEvent, hexapod: &mut Hexapod, conf: &Config){
-        let desired = Vector3::new(event.lx * 15.0, event.ly * 15.0, BODY_DIST_TO_GROUND);
-        let difference = desired - hexapod.bodyTransform.translation;
-        hexapod.bodyTransform.translation += difference.with_max_length(conf.walking_translating_resolution*0.5);
+    fn name(&self) -> &'static str { "move_body" }
 
-        let desired = Vector2::new(event.rx, event.ry) * 0.25;
-        let difference = desired - self.current_rot;
-        self.current_rot += difference.with_max_length(conf.walking_rotating_resolution.radians);
-        hexapod.bodyTransform.rotation = Rotation3D::euler(Angle::radians(-self.current_rot.y), Angle::radians(self.current_rot.x), Angle::radians(0.));
+    //the left stick shifts the body and the right stick tilts it.
+    //While TURN_BODY is held the left stick controls the yaw and height instead
+    fn handle_input(&mut self, event: &ControllerEvent, actions: &Actions, hexapod: &mut Hexapod, conf: &Config){
+        let left = Vector2::new(event.lx, event.ly);
+        let (shift, yaw_height) = if actions.held(Action::TURN_BODY) { (Vector2::zero(), left) } else { (left, Vector2::zero()) };
+
+        let range = &conf.body_pose_range;
+        let desired = BodyPose::new(
+            shift.x * range.x, shift.y * range.y, yaw_height.y * range.z,
+            event.rx * range.roll, -event.ry * range.pitch, -yaw_height.x * range.yaw
+        );
+        if desired != self.stick_pose {
+            self.stick_pose = desired;
+            self.handle_command(&Command::BODY_POSE(desired), hexapod, conf);
+        }
+
+        let target = self.target;
+        self.move_towards(&target, hexapod, conf);
+    }
+
+    fn handle_command(&mut self, command: &Command, _hexapod: &mut Hexapod, _conf: &Config) -> bool {
+        match command {
+            Command::BODY_POSE(pose) => self.target = *pose,
+            Command::STOP => self.target = BodyPose::neutral(),
+            _ => return false,
+        }
+        true
     }
 
     fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
-        self.handle_input(&ControllerEvent::default(), hexapod, conf);
-        let offset = hexapod.bodyTransform.translation - Vector3::new(0.0, 0.0, BODY_DIST_TO_GROUND);
-        offset.length() < 0.1
+        self.target = BodyPose::neutral();
+        self.move_towards(&BodyPose::neutral(), hexapod, conf)
     }
-}
\ No newline at end of file
+
+    fn exit(&mut self, _hexapod: &mut Hexapod, _conf: &Config) {
+        self.pose = BodyPose::neutral();
+        self.target = BodyPose::neutral();
+        self.stick_pose = BodyPose::neutral();
+    }
+}
+
+impl MoveBodyMode {
+    fn move_towards(&mut self, desired: &BodyPose, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        let target = desired.clamp_reachable(hexapod, conf.stance.height);
+        let reached = self.pose.step_towards(&target, conf);
+        hexapod.bodyTransform = self.pose.to_isometry(conf.stance.height);
+        reached
+    }
+}
@@ -0,0 +1,59 @@
+use crate::modes::Mode;
+use crate::modes::gait::GaitEngine;
+use crate::modes::move_body::MoveBodyMode;
+use crate::modes::look_at::LookAtMode;
+use crate::modes::single_leg::SingleLegMode;
+use crate::modes::dance::DanceMode;
+
+pub type ModeConstructor = fn() -> Box<dyn Mode>;
+
+//knows how to create every mode by its name, the InputHandler builds its mode list from the
+//names in conf.modes. Modes from other crates can be added with register
+pub struct ModeRegistry {
+    modes: Vec<(&'static str, ModeConstructor)>,
+}
+
+impl ModeRegistry {
+    pub fn empty() -> Self {
+        Self { modes: Vec::new() }
+    }
+
+    //a mode that is registered again under the same name replaces the old one
+    pub fn register<M: Mode + 'static>(&mut self) {
+        let name = M::new().name();
+        let constructor: ModeConstructor = || Box::new(M::new());
+        self.modes.retain(|(n, _)| *n != name);
+        self.modes.push((name, constructor));
+    }
+
+    pub fn create(&self, name: &str) -> Option<Box<dyn Mode>> {
+        self.modes.iter().find(|(n, _)| *n == name).map(|(_, constructor)| constructor())
+    }
+
+    //unknown names are skipped
+    pub fn create_all(&self, names: &[String]) -> Vec<Box<dyn Mode>> {
+        names.iter().filter_map(|name| {
+            let mode = self.create(name);
+            if mode.is_none() {
+                eprintln!("unknown mode: {}", name);
+            }
+            mode
+        }).collect()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.modes.iter().map(|(name, _)| *name).collect()
+    }
+}
+
+impl Default for ModeRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register::<GaitEngine>();
+        registry.register::<MoveBodyMode>();
+        registry.register::<LookAtMode>();
+        registry.register::<SingleLegMode>();
+        registry.register::<DanceMode>();
+        registry
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{Point3, Rotation3, Vector2, Vector3};
+use crate::actions::{Action, Actions};
+use crate::config::Config;
+use crate::hexapod::{Hexapod, Foot};
+use crate::input_handler::ControllerEvent;
+use crate::modes::Mode;
+
+//lifts a single leg and moves its foot with the sticks. Before the leg is lifted the body
+//shifts over the other five feet, which stay on the ground and keep the body level
+pub struct SingleLegMode {
+    state: State,
+    foot: Foot,
+    foot_offset: Vector3, //position of the lifted foot relative to its standing position
+    body_shift: Vector2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+enum State {
+    SHIFTING(Foot),
+    LIFTED(Foot),
+    PLACING(Foot, Option<Foot>), //moves the foot back down, then continues with the next foot (if any)
+    CENTERING,
+}
+
+impl Mode for SingleLegMode {
+    fn new() -> Self {
+        Self {
+            state: State::SHIFTING(Foot::RIGHT_FRONT),
+            foot: Foot::RIGHT_FRONT,
+            foot_offset: Vector3::zero(),
+            body_shift: Vector2::zero(),
+        }
+    }
+
+    fn name(&self) -> &'static str { "single_leg" }
+
+    //NEXT_LEG/PREV_LEG selects the leg, the left stick moves the foot horizontally and the right stick up and down
+    fn handle_input(&mut self, event: &ControllerEvent, actions: &Actions, hexapod: &mut Hexapod, conf: &Config) {
+        if let State::LIFTED(foot) = self.state {
+            if actions.triggered(Action::NEXT_LEG) {
+                self.foot = foot.next();
+                self.state = State::PLACING(foot, Some(self.foot));
+            } else if actions.triggered(Action::PREV_LEG) {
+                self.foot = foot.prev();
+                self.state = State::PLACING(foot, Some(self.foot));
+            } else {
+                let delta = Vector3::new(event.lx, event.ly, event.ry) * conf.single_leg_speed;
+                self.move_foot(foot, self.foot_offset + delta, hexapod, conf);
+            }
+        }
+        self.update(hexapod, conf);
+    }
+
+    fn return_to_idle(&mut self, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        self.state = match self.state {
+            State::LIFTED(foot) => State::PLACING(foot, None),
+            State::SHIFTING(_) => State::CENTERING,
+            other => other,
+        };
+        self.update(hexapod, conf);
+
+        self.state == State::CENTERING && self.body_shift.length() < 0.1
+    }
+
+    //all feet are on the ground unless a leg is lifted or being placed
+    fn can_blend(&self) -> bool {
+        matches!(self.state, State::SHIFTING(_) | State::CENTERING)
+    }
+
+    //the lifted foot and the ones carrying the robot are placed relative to the stance positions
+    fn can_adjust_stance(&self) -> bool {
+        false
+    }
+
+    fn exit(&mut self, _hexapod: &mut Hexapod, _conf: &Config) {
+        self.state = State::SHIFTING(self.foot);
+        self.foot_offset = Vector3::zero();
+        self.body_shift = Vector2::zero();
+    }
+}
+
+impl SingleLegMode {
+    fn update(&mut self, hexapod: &mut Hexapod, conf: &Config) {
+        let max_step = conf.walking_translating_resolution * 0.5;
+        self.state = match self.state {
+            State::SHIFTING(foot) => {
+                if self.shift_body(support_center(foot, hexapod), hexapod, conf) {
+                    self.foot_offset = Vector3::zero();
+                    State::LIFTED(foot)
+                } else {
+                    State::SHIFTING(foot)
+                }
+            },
+            State::LIFTED(foot) => {
+                //lift the foot off the ground after the weight has been shifted
+                if self.foot_offset.z < conf.single_leg_lift_height {
+                    let offset = self.foot_offset + Vector3::new(0.0, 0.0, max_step);
+                    self.move_foot(foot, offset, hexapod, conf);
+                }
+                State::LIFTED(foot)
+            },
+            State::PLACING(foot, next) => {
+                //first move the foot above its standing position, then lower it
+                let mut target = Vector3::new(0.0, 0.0, self.foot_offset.z.max(conf.single_leg_lift_height));
+                if self.foot_offset.xy().length() < 0.1 {
+                    target.z = 0.0;
+                }
+                self.foot_offset += (target - self.foot_offset).with_max_length(max_step);
+                hexapod.set_abs_foot_pos(foot, foot_pos(foot, self.foot_offset, hexapod, conf));
+
+                if self.foot_offset.length() > 0.1 {
+                    State::PLACING(foot, next)
+                } else if let Some(next) = next {
+                    State::SHIFTING(next)
+                } else {
+                    State::CENTERING
+                }
+            },
+            State::CENTERING => {
+                self.shift_body(Vector2::zero(), hexapod, conf);
+                State::CENTERING
+            },
+        };
+    }
+
+    //only moves the foot if it stays above the ground and within reach of its leg
+    fn move_foot(&mut self, foot: Foot, offset: Vector3, hexapod: &mut Hexapod, conf: &Config) {
+        if offset.z < 0.0 {
+            return;
+        }
+        let previous = hexapod.get_abs_foot_pos(foot);
+        hexapod.set_abs_foot_pos(foot, foot_pos(foot, offset, hexapod, conf));
+        if hexapod.is_reachable(&hexapod.bodyTransform) {
+            self.foot_offset = offset;
+        } else {
+            hexapod.set_abs_foot_pos(foot, previous);
+        }
+    }
+
+    fn shift_body(&mut self, target: Vector2, hexapod: &mut Hexapod, conf: &Config) -> bool {
+        let difference = target - self.body_shift;
+        self.body_shift += difference.with_max_length(conf.walking_translating_resolution * 0.5);
+        hexapod.bodyTransform.rotation = Rotation3::identity();
+        hexapod.bodyTransform.translation = self.body_shift.extend(conf.stance.height);
+        difference.length() < 0.1
+    }
+}
+
+fn foot_pos(foot: Foot, offset: Vector3, hexapod: &Hexapod, conf: &Config) -> Point3 {
+    hexapod.origin.transform_point3(conf.stance.foot_pos(foot) + offset)
+}
+
+//center of the feet that stay on the ground (relative to the origin)
+fn support_center(lifted: Foot, hexapod: &Hexapod) -> Vector2 {
+    let support = Foot::all().iter().filter(|f| **f != lifted)
+        .map(|f| hexapod.origin.inv_transform_point3(hexapod.get_abs_foot_pos(*f)).to_vector().xy())
+        .collect::<Vec<_>>();
+    support.iter().copied().sum::<Vector2>() / support.len() as f64
+}
@@ -1,17 +1,66 @@
-use serde::{Serialize};
+use serde::{Serialize, Deserialize};
+use crate::GaitType;
+use crate::input_handler::{Button, ControllerEvent};
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TelemetryMessage{
+    pub sequence: u64, //counts up with every message
+    pub timestamp: f64, //seconds since the InputHandler was created, never goes backwards
+    pub mode: String,
+    pub link: LinkState,
+    pub gait: Option<GaitTelemetry>, //only while the active mode walks
+    pub input: ControllerEvent, //after the input shaping, as the modes see it
+    pub consumed_buttons: Vec<Button>, //bound by the active mode itself, see actions::consumed_buttons
+    pub timing: LoopTiming,
     pub center: [f64; 2],
     pub rotation: f64,
+    pub body: TransformTelemetry, //bodyTransform, relative to the center
     pub legs: [LegTelemetry; 6],
-    pub angles: [Option<f64>; 6*3]
+    pub angles: [Option<f64>; 6*3],
+    pub unreachable: [bool; 6], //legs that can't reach their foot, their angles are None
 }
 
-#[derive(Serialize, Debug)]
+//state of the connection to the controller
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+pub enum LinkState {
+    #[default]
+    WAITING, //no event received yet
+    CONNECTED,
+    LOST, //no event within conf.controller_timeout, the robot settles into its neutral pose
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LegTelemetry {
     pub joint: [f64; 3],
     pub hip: [f64; 3],
     pub knee: [f64; 3],
     pub foot: [f64; 3],
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct GaitTelemetry {
+    pub gait: GaitType,
+    //position in the gait cycle in steps, 0 while standing. It counts up from 0 when the robot
+    //starts walking and after the first cycle wraps around between one and two cycle lengths
+    pub step_progress: f64,
+    pub feet: [FootContact; 6],
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum FootContact {
+    STANDING,
+    STEPPING,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct TransformTelemetry {
+    pub translation: [f64; 3],
+    pub rotation: [f64; 4], //quaternion as x, y, z, w
+}
+
+//filled in by the main loop, in seconds
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone)]
+pub struct LoopTiming {
+    pub period: f64, //time between the start of the last two control ticks
+    pub busy: f64, //time the last tick took without sleeping
+}
@@ -0,0 +1,95 @@
+use std::fmt::Write;
+use crate::config::{BODY_HEIGHT, BODY_THICKNESS, BODY_WIDTH_MIDDLE, HIP_LENGTH, LEG_THICKNESS, LOWER_LEG_LENGTH,
+    SERVO_LIMITS, SERVO_SPEED, SERVO_TORQUE, UPPER_LEG_LENGTH};
+use crate::hexapod::Foot;
+
+const MM_TO_M: f64 = 0.001; //the robot works in mm, URDF in m
+
+//a URDF description of the robot for RViz, simulators and kinematics libraries. It is generated
+//from the dimensions in config.rs and Foot::leg_joint_pos/leg_joint_orientation, so it never has
+//to be edited by hand, the checked in hexapod/hexapod.urdf is regenerated with
+//cargo run -p io_utils --bin export_urdf hexapod/hexapod.urdf. The frames are the ones of the
+//telemetry: x to the right, y to the front and z up. The joints are named like in the Foxglove
+//joint states (Foot::joint_names), their angles are the ones of Hexapod::get_angles, all zero
+//with the upper leg horizontal and the lower leg pointing down. There are no inertial values,
+//the masses aren't known
+pub fn urdf() -> String {
+    let mut urdf = String::from("<?xml version=\"1.0\"?>\n<!-- generated by hexapod::urdf, don't edit -->\n<robot name=\"hexapod\">\n");
+    link(&mut urdf, "base_link", &box_shape([0.0; 3], [BODY_WIDTH_MIDDLE, BODY_HEIGHT, BODY_THICKNESS]));
+
+    for foot in Foot::all() {
+        let [hip, upper_leg, lower_leg] = foot.joint_names();
+        let position = foot.leg_joint_pos();
+        let orientation = foot.leg_joint_orientation();
+        let yaw = orientation.y.atan2(orientation.x); //angle_from_x_axis only approximates it
+
+        //every link starts at its joint and points along x, the lower leg points down
+        link(&mut urdf, &hip, &box_shape([HIP_LENGTH / 2.0, 0.0, 0.0], [HIP_LENGTH, LEG_THICKNESS, LEG_THICKNESS]));
+        link(&mut urdf, &upper_leg, &box_shape([UPPER_LEG_LENGTH / 2.0, 0.0, 0.0], [UPPER_LEG_LENGTH, LEG_THICKNESS, LEG_THICKNESS]));
+        link(&mut urdf, &lower_leg, &box_shape([0.0, 0.0, -LOWER_LEG_LENGTH / 2.0], [LEG_THICKNESS, LEG_THICKNESS, LOWER_LEG_LENGTH]));
+        let foot_link = format!("{:?}_foot", foot).to_lowercase();
+        link(&mut urdf, &foot_link, &sphere_shape(LEG_THICKNESS / 2.0));
+
+        //the hip servo turns counterclockwise, the two others lift the leg for positive angles
+        revolute(&mut urdf, &hip, "base_link", [position.x, position.y, 0.0], yaw, [0, 0, 1], SERVO_LIMITS[0]);
+        revolute(&mut urdf, &upper_leg, &hip, [HIP_LENGTH, 0.0, 0.0], 0.0, [0, -1, 0], SERVO_LIMITS[1]);
+        revolute(&mut urdf, &lower_leg, &upper_leg, [UPPER_LEG_LENGTH, 0.0, 0.0], 0.0, [0, -1, 0], SERVO_LIMITS[2]);
+        writeln!(urdf, "  <joint name=\"{}\" type=\"fixed\">", foot_link).unwrap();
+        writeln!(urdf, "    <parent link=\"{}\"/>\n    <child link=\"{}\"/>", lower_leg, foot_link).unwrap();
+        writeln!(urdf, "    <origin xyz=\"{}\" rpy=\"0 0 0\"/>\n  </joint>", xyz([0.0, 0.0, -LOWER_LEG_LENGTH])).unwrap();
+    }
+    urdf.push_str("</robot>\n");
+    urdf
+}
+
+//the joint's link is the child and has the same name
+fn revolute(urdf: &mut String, name: &str, parent: &str, origin: [f64; 3], yaw: f64, axis: [i8; 3], limits: (f64, f64)) {
+    writeln!(urdf, "  <joint name=\"{}\" type=\"revolute\">", name).unwrap();
+    writeln!(urdf, "    <parent link=\"{}\"/>\n    <child link=\"{}\"/>", parent, name).unwrap();
+    writeln!(urdf, "    <origin xyz=\"{}\" rpy=\"0 0 {}\"/>", xyz(origin), number(yaw)).unwrap();
+    writeln!(urdf, "    <axis xyz=\"{} {} {}\"/>", axis[0], axis[1], axis[2]).unwrap();
+    writeln!(urdf, "    <limit lower=\"{}\" upper=\"{}\" effort=\"{}\" velocity=\"{}\"/>",
+        number(limits.0), number(limits.1), number(SERVO_TORQUE), number(SERVO_SPEED)).unwrap();
+    writeln!(urdf, "  </joint>").unwrap();
+}
+
+//the shape is used for the visual and the collision
+fn link(urdf: &mut String, name: &str, shape: &str) {
+    writeln!(urdf, "  <link name=\"{}\">", name).unwrap();
+    for element in ["visual", "collision"] {
+        writeln!(urdf, "    <{}>\n{}    </{}>", element, shape, element).unwrap();
+    }
+    writeln!(urdf, "  </link>").unwrap();
+}
+
+fn box_shape(center: [f64; 3], size: [f64; 3]) -> String {
+    format!("      <origin xyz=\"{}\" rpy=\"0 0 0\"/>\n      <geometry><box size=\"{}\"/></geometry>\n", xyz(center), xyz(size))
+}
+
+fn sphere_shape(radius: f64) -> String {
+    format!("      <geometry><sphere radius=\"{}\"/></geometry>\n", number(radius * MM_TO_M))
+}
+
+//in m
+fn xyz(v: [f64; 3]) -> String {
+    v.map(|v| number(v * MM_TO_M)).join(" ")
+}
+
+//rounded to a µm or µrad, without trailing zeros
+fn number(v: f64) -> String {
+    let s = format!("{:.6}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_in_urdf_is_up_to_date() {
+        assert!(urdf() == include_str!("../hexapod.urdf"),
+            "hexapod/hexapod.urdf is outdated, regenerate it with cargo run -p io_utils --bin export_urdf hexapod/hexapod.urdf");
+    }
+}
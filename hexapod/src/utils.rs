@@ -1,3 +1,6 @@
+use serde::de::DeserializeOwned;
+use serde::de::value::{Error, StrDeserializer};
+
 #[inline]
 pub fn clamp_abs(val: f64, max: f64) -> f64 {
     if val.abs() < max {
@@ -5,4 +8,29 @@ pub fn clamp_abs(val: f64, max: f64) -> f64 {
     } else {
         val.signum() * max
     }
-}
\ No newline at end of file
+}
+
+//eases in and out, maps 0 to 0 and 1 to 1
+#[inline]
+pub fn smoothstep(t: f64) -> f64 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+//the variant of a serde enum with the given name as written in the enum, e.g. "ZR" or "CBOR"
+pub fn from_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    T::deserialize(StrDeserializer::<Error>::new(name)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_handler::Button;
+
+    #[test]
+    fn parses_enum_names() {
+        assert_eq!(from_name::<Button>("ZR"), Some(Button::ZR));
+        assert_eq!(from_name::<Button>("zr"), None);
+        assert_eq!(from_name::<Button>(""), None);
+    }
+}
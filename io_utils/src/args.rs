@@ -0,0 +1,15 @@
+use std::error::Error;
+use hexapod::config::Config;
+
+//the value after a flag, e.g. the file of "--config <file>"
+pub fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1))
+}
+
+//every binary takes the configuration of the robot with --config <file>, the defaults without it
+pub fn load_config(args: &[String]) -> Result<Config, Box<dyn Error>> {
+    match arg_value(args, "--config") {
+        Some(path) => Config::load(path),
+        None => Ok(Config::default()),
+    }
+}
@@ -0,0 +1,15 @@
+//writes the URDF description of the robot (see hexapod::urdf) to a file or stdout, run it again
+//after the dimensions in hexapod::config changed
+use std::env;
+use std::fs;
+
+fn main() {
+    let urdf = hexapod::urdf::urdf();
+    match env::args().nth(1) {
+        Some(path) => {
+            fs::write(&path, urdf).unwrap();
+            println!("wrote {}", path);
+        },
+        None => print!("{}", urdf),
+    }
+}
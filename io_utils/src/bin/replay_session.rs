@@ -0,0 +1,17 @@
+//replays a recorded controller session without the robot and prints the telemetry
+//of every control loop iteration as json lines
+use std::env;
+use io_utils::args::load_config;
+use io_utils::recording::{load_session, replay_session};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let path = args.get(1).filter(|a| !a.starts_with("--")).expect("usage: replay_session <recording> [--config <file>]");
+    let events = load_session(path).unwrap();
+
+    //the same configuration as on the robot is needed to get the same result
+    let conf = load_config(&args).unwrap();
+    for msg in replay_session(events, conf) {
+        println!("{}", serde_json::to_string(&msg).unwrap());
+    }
+}
@@ -0,0 +1,55 @@
+//streams a telemetry log back out through the telemetry server, so the viewers show a past run as
+//if it was live. Runs in real time by default, --speed scales it and --from starts at a timestamp
+//usage: replay_telemetry <log file or directory> [--speed <factor>] [--from <seconds>] [--config <file>]
+use std::env;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use io_utils::args::{arg_value, load_config};
+use io_utils::foxglove::FoxgloveServer;
+use io_utils::telemetry::TelemetryReporter;
+use io_utils::telemetry_log::{log_files, read_log};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let path = args.get(1).filter(|a| !a.starts_with("--"))
+        .expect("usage: replay_telemetry <log file or directory> [--speed <factor>] [--from <seconds>] [--config <file>]");
+    let speed: f64 = arg_value(&args, "--speed").map_or(1.0, |s| s.parse().expect("invalid speed"));
+    let from: f64 = arg_value(&args, "--from").map_or(0.0, |s| s.parse().expect("invalid start time"));
+    assert!(speed > 0.0, "the speed has to be positive");
+
+    //the viewers connect to the same addresses as on the robot
+    let conf = load_config(&args).unwrap();
+    let mut telemetry = TelemetryReporter::new(&conf.telemetry_address).unwrap();
+    telemetry.listen_websocket(&conf.websocket_address).unwrap();
+    let mut foxglove = FoxgloveServer::new(&conf.foxglove_address, conf.foxglove_publish_rate).unwrap();
+
+    let files = log_files(path).unwrap();
+    if files.is_empty() {
+        eprintln!("no telemetry logs in {}", path);
+        return;
+    }
+    //the time of the first message of a run and when it was replayed
+    let mut start: Option<(f64, Instant)> = None;
+    let mut last_timestamp = f64::NEG_INFINITY;
+    for file in files {
+        println!("replaying {}", file.display());
+        for msg in read_log(&file, from).unwrap() {
+            //the timestamps start at zero again after the robot was restarted
+            if msg.timestamp < last_timestamp {
+                start = None;
+            }
+            last_timestamp = msg.timestamp;
+            let (first, started) = *start.get_or_insert((msg.timestamp, Instant::now()));
+
+            let time = Duration::from_secs_f64((msg.timestamp - first) / speed);
+            if let Some(remaining) = time.checked_sub(started.elapsed()) {
+                sleep(remaining);
+            }
+            for request in telemetry.requests() {
+                eprintln!("ignoring {:?} during a replay", request);
+            }
+            telemetry.report(&msg);
+            foxglove.publish(&msg);
+        }
+    }
+}
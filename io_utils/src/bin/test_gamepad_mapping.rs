@@ -0,0 +1,87 @@
+//checks the gamepad mapping from the config (--config <file>, or the default one) with a virtual uinput device:
+//every mapped stick axis and button is moved once and the resulting ControllerEvent is compared.
+//Needs write access to /dev/uinput
+use std::env;
+use std::error::Error;
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+use evdev::{AbsInfo, AbsoluteAxisCode, AttributeSet, EventType, InputEvent, KeyCode, UinputAbsSetup};
+use evdev::uinput::VirtualDevice;
+use hexapod::input_handler::{Button, ControllerEvent};
+use io_utils::args::load_config;
+use io_utils::input::InputSource;
+use io_utils::input::gamepad::{GamepadSource, ParsedMapping};
+
+const AXIS_MIN: i32 = -32768;
+const AXIS_MAX: i32 = 32767;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let conf = load_config(&env::args().collect::<Vec<_>>())?;
+    let ParsedMapping { sticks, keys, axis_buttons, .. } = ParsedMapping::parse(&conf.gamepad)?;
+
+    let mut axes: Vec<AbsoluteAxisCode> = sticks.iter().chain(axis_buttons.iter().map(|(a, _)| a)).map(|a| a.code).collect();
+    axes.sort_by_key(|code| code.0);
+    axes.dedup();
+    let mut builder = VirtualDevice::builder()?
+        .name("hexapod test gamepad")
+        .with_keys(&keys.iter().map(|(code, _)| *code).collect::<AttributeSet<KeyCode>>())?;
+    for code in axes.iter() {
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(*code, AbsInfo::new(0, AXIS_MIN, AXIS_MAX, 0, 0, 0)))?;
+    }
+    let mut device = builder.build()?;
+    let path = device.enumerate_dev_nodes_blocking()?.next().ok_or("virtual device has no device node")??;
+    sleep(Duration::from_millis(200)); //give udev some time to set up the permissions
+
+    let mut source = GamepadSource::new(path.to_str(), &conf.gamepad)?;
+    source.connect()?;
+    let mut failures = 0;
+    let mut check = |name: String, ok: bool| {
+        println!("{} {}", if ok { "ok  " } else { "FAIL" }, name);
+        if !ok { failures += 1 }
+    };
+
+    for (i, (axis, name)) in sticks.iter().zip(["lx", "ly", "rx", "ry"]).enumerate() {
+        let event = emit(&mut device, &mut source, abs(axis.code, AXIS_MAX))?;
+        let value = [event.lx, event.ly, event.rx, event.ry][i];
+        let expected = if axis.invert { -1.0 } else { 1.0 };
+        check(format!("{} ({:?}) = {:.2}", name, axis.code, value), (value - expected).abs() < 0.01);
+        emit(&mut device, &mut source, abs(axis.code, 0))?;
+    }
+
+    for (code, button) in keys.iter() {
+        let event = emit(&mut device, &mut source, InputEvent::new(EventType::KEY.0, code.0, 1))?;
+        check(format!("{:?} -> {:?}", code, button), only(&event, *button));
+        emit(&mut device, &mut source, InputEvent::new(EventType::KEY.0, code.0, 0))?;
+    }
+
+    for (axis, button) in axis_buttons.iter() {
+        let value = if axis.invert { AXIS_MIN } else { AXIS_MAX };
+        let event = emit(&mut device, &mut source, abs(axis.code, value))?;
+        check(format!("{}{:?} -> {:?}", if axis.invert { "-" } else { "+" }, axis.code, button), only(&event, *button));
+        emit(&mut device, &mut source, abs(axis.code, 0))?;
+    }
+
+    if failures > 0 {
+        eprintln!("{} mappings failed", failures);
+        exit(1);
+    }
+    Ok(())
+}
+
+fn abs(code: AbsoluteAxisCode, value: i32) -> InputEvent {
+    InputEvent::new(EventType::ABSOLUTE.0, code.0, value)
+}
+
+fn emit(device: &mut VirtualDevice, source: &mut GamepadSource, event: InputEvent) -> Result<ControllerEvent, Box<dyn Error>> {
+    device.emit(&[event])?;
+    Ok(source.receive()?)
+}
+
+//the button is pressed and triggered and no other one is
+fn only(event: &ControllerEvent, button: Button) -> bool {
+    Button::all().iter().all(|b| {
+        let expected = *b == button;
+        event.pressed(*b) == expected && event.triggered(*b) == expected
+    })
+}
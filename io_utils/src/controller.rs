@@ -1,52 +1,247 @@
-use std::net::{TcpStream, ToSocketAddrs};
-use std::io::prelude::*;
 use std::convert::TryInto;
-use std::sync::mpsc::{self, Sender, Receiver};
-use std::thread;
+use std::io::{self, Read};
+use hexapod::config::ControllerProtocol;
 use hexapod::input_handler::ControllerEvent;
 
-pub fn capture_thread_inner<A: ToSocketAddrs>(addr: A, sender: Sender<ControllerEvent>) -> std::io::Result<()> {
-    let mut stream = TcpStream::connect(addr)?;
+//the legacy message and the start of every frame payload: four big-endian i16 stick values,
+//12 pressed bytes and 12 triggered bytes
+pub const MESSAGE_SIZE: usize = 32;
 
+//a frame is
+//  magic     2 bytes  "HX"
+//  version   1 byte
+//  length    2 bytes  length of the payload
+//  sequence  4 bytes  counts up with every frame
+//  payload   the message, followed by lt, rt, dpad_x and dpad_y as big-endian i16 since version 1
+//  crc       4 bytes  CRC-32 of everything from the version to the end of the payload
+//all numbers are big-endian. Newer versions only append fields to the payload, so older
+//receivers can still read them and payloads of older clients leave the new fields at 0
+pub const MAGIC: [u8; 2] = *b"HX";
+pub const VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 9;
+pub const CRC_SIZE: usize = 4;
+pub const MAX_PAYLOAD_SIZE: usize = 256;
+const EXTRA_AXES: usize = 4;
+
+//The timestamp is left at 0, the input source sets it
+pub fn decode_message(msg: &[u8; MESSAGE_SIZE]) -> ControllerEvent {
     let mut pressed = [false; 12];
     let mut triggered = [false; 12];
-    for timestamp in 0.. {
-        let mut msg = [0u8; 32];
-        stream.read_exact(&mut msg)?;
 
-        pressed.iter_mut()
-            .zip(msg[8..].iter())
-            .for_each(|(p, e)| *p = *e != 0);
+    pressed.iter_mut()
+        .zip(msg[8..].iter())
+        .for_each(|(p, e)| *p = *e != 0);
+
+    triggered.iter_mut()
+        .zip(msg[20..].iter())
+        .for_each(|(p, e)| *p = *e != 0);
+
+    ControllerEvent{
+        timestamp: 0,
+        lx: stick_input_from_bytes(&msg[0..2]),
+        ly: stick_input_from_bytes(&msg[2..4]),
+        rx: stick_input_from_bytes(&msg[4..6]),
+        ry: stick_input_from_bytes(&msg[6..8]),
+        pressed,
+        triggered,
+        ..Default::default()
+    }
+}
+
+//the payload has to contain at least the message, the extra axes are read as far as present
+fn decode_payload(payload: &[u8]) -> ControllerEvent {
+    let mut event = decode_message(payload[..MESSAGE_SIZE].try_into().unwrap());
+    let mut extra = [0.0; EXTRA_AXES];
+    for (i, bytes) in payload[MESSAGE_SIZE..].chunks_exact(2).take(EXTRA_AXES).enumerate() {
+        extra[i] = stick_input_from_bytes(bytes);
+    }
+    [event.lt, event.rt, event.dpad_x, event.dpad_y] = extra;
+    event
+}
+
+pub fn encode_frame(event: &ControllerEvent, sequence: u32) -> Vec<u8> {
+    let axes = [event.lx, event.ly, event.rx, event.ry];
+    let extra = [event.lt, event.rt, event.dpad_x, event.dpad_y];
+    let mut payload: Vec<u8> = axes.iter().flat_map(|a| stick_input_to_bytes(*a)).collect();
+    payload.extend(event.pressed.iter().map(|p| *p as u8));
+    payload.extend(event.triggered.iter().map(|t| *t as u8));
+    payload.extend(extra.iter().flat_map(|a| stick_input_to_bytes(*a)));
+
+    let mut frame = MAGIC.to_vec();
+    frame.push(VERSION);
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&sequence.to_be_bytes());
+    frame.extend_from_slice(&payload);
+    let crc = crc32fast::hash(&frame[MAGIC.len()..]);
+    frame.extend_from_slice(&crc.to_be_bytes());
+    frame
+}
+
+//finds the frames in a stream of bytes. After a corrupted or lost byte everything up to the
+//next magic bytes is skipped, so only the broken frame is lost
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    last_sequence: Option<u32>,
+    pub skipped_bytes: u64,
+    pub lost_frames: u64, //gaps in the sequence numbers
+}
+
+//frames that are this far behind the last one are from a client that started over, as are
+//frames with a small sequence that is closer to 0 than to the last one
+const SEQUENCE_RESTART: u32 = 1000;
+
+impl FrameDecoder {
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    pub fn next_event(&mut self) -> Option<ControllerEvent> {
+        loop {
+            let start = match self.buffer.windows(MAGIC.len()).position(|w| w == MAGIC) {
+                Some(start) => start,
+                None => {
+                    //the last byte could be the start of the magic bytes
+                    let keep = if self.buffer.last() == Some(&MAGIC[0]) { 1 } else { 0 };
+                    self.skip(self.buffer.len() - keep);
+                    return None;
+                },
+            };
+            self.skip(start);
+            if self.buffer.len() < HEADER_SIZE {
+                return None;
+            }
+
+            let version = self.buffer[2];
+            let length = u16::from_be_bytes(self.buffer[3..5].try_into().unwrap()) as usize;
+            if version == 0 || !(MESSAGE_SIZE..=MAX_PAYLOAD_SIZE).contains(&length) {
+                self.skip(1);
+                continue;
+            }
+            let end = HEADER_SIZE + length;
+            if self.buffer.len() < end + CRC_SIZE {
+                return None;
+            }
+            let crc = u32::from_be_bytes(self.buffer[end..end + CRC_SIZE].try_into().unwrap());
+            if crc32fast::hash(&self.buffer[MAGIC.len()..end]) != crc {
+                self.skip(1);
+                continue;
+            }
+
+            if self.skipped_bytes > 0 {
+                eprintln!("controller stream resynchronized, skipped {} bytes", self.skipped_bytes);
+                self.skipped_bytes = 0;
+            }
+            let sequence = u32::from_be_bytes(self.buffer[5..9].try_into().unwrap());
+            let event = decode_payload(&self.buffer[HEADER_SIZE..end]);
+            self.buffer.drain(..end + CRC_SIZE);
+            if self.is_new(sequence) {
+                return Some(event);
+            }
+        }
+    }
 
-        triggered.iter_mut()
-            .zip(msg[20..].iter())
-            .for_each(|(p, e)| *p = *e != 0);
+    //drops duplicated and reordered frames and counts the lost ones
+    fn is_new(&mut self, sequence: u32) -> bool {
+        if let Some(last) = self.last_sequence {
+            let behind = last.wrapping_sub(sequence);
+            let restarted = sequence < SEQUENCE_RESTART && sequence < behind;
+            if behind < SEQUENCE_RESTART && !restarted {
+                return false;
+            }
+            let ahead = sequence.wrapping_sub(last);
+            if ahead < u32::MAX / 2 {
+                self.lost_frames += ahead as u64 - 1;
+            }
+        }
+        self.last_sequence = Some(sequence);
+        true
+    }
 
-        let event = ControllerEvent{
-            timestamp,
-            lx: stick_input_from_bytes(&msg[0..2]),
-            ly: stick_input_from_bytes(&msg[2..4]),
-            rx: stick_input_from_bytes(&msg[4..6]),
-            ry: stick_input_from_bytes(&msg[6..8]),
-            pressed,
-            triggered
-        };
-        sender.send(event).unwrap();
+    fn skip(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.skipped_bytes += count as u64;
     }
+}
 
-    Ok(())
+//reads controller events with the configured protocol, one reader per connection
+pub struct MessageReader {
+    protocol: ControllerProtocol,
+    frames: FrameDecoder,
 }
 
-pub fn start_controller_thread(addr: &'static str) -> Receiver<ControllerEvent> {
-    let (tx, rx): (Sender<ControllerEvent>, Receiver<ControllerEvent>) = mpsc::channel();
-    thread::spawn(move || {
-        capture_thread_inner(addr, tx).unwrap();
-    });
+impl MessageReader {
+    pub fn new(protocol: ControllerProtocol) -> Self {
+        Self {
+            protocol,
+            frames: FrameDecoder::default(),
+        }
+    }
+
+    //blocks until a complete event has been read from the stream
+    pub fn read_event<R: Read>(&mut self, stream: &mut R) -> io::Result<ControllerEvent> {
+        if self.protocol == ControllerProtocol::LEGACY {
+            let mut msg = [0u8; MESSAGE_SIZE];
+            stream.read_exact(&mut msg)?;
+            return Ok(decode_message(&msg));
+        }
 
-    rx
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(event) = self.frames.next_event() {
+                return Ok(event);
+            }
+            let len = stream.read(&mut buf)?;
+            if len == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.frames.push(&buf[..len]);
+        }
+    }
+
+    //every datagram contains one message or frame, broken ones are dropped
+    pub fn decode_datagram(&mut self, datagram: &[u8]) -> Option<ControllerEvent> {
+        match self.protocol {
+            ControllerProtocol::LEGACY => datagram.try_into().ok().map(decode_message),
+            ControllerProtocol::FRAMED => {
+                self.frames.push(datagram);
+                self.frames.next_event()
+            },
+        }
+    }
 }
 
 fn stick_input_from_bytes(bytes: &[u8]) -> f64 {
     let integer = i16::from_be_bytes(bytes.try_into().unwrap());
     integer as f64 / i16::MAX as f64
-}
\ No newline at end of file
+}
+
+fn stick_input_to_bytes(value: f64) -> [u8; 2] {
+    ((value.clamp(-1.0, 1.0) * i16::MAX as f64).round() as i16).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequences(decoder: &mut FrameDecoder, sequences: &[u32]) -> Vec<bool> {
+        sequences.iter().map(|s| {
+            decoder.push(&encode_frame(&ControllerEvent::default(), *s));
+            decoder.next_event().is_some()
+        }).collect()
+    }
+
+    #[test]
+    fn accepts_a_client_that_restarts_at_zero() {
+        let mut decoder = FrameDecoder::default();
+        assert_eq!(sequences(&mut decoder, &[1, 2, 3, 4, 5, 0, 1, 2]), [true; 8]);
+        assert_eq!(decoder.lost_frames, 0);
+    }
+
+    #[test]
+    fn drops_duplicated_and_reordered_frames() {
+        let mut decoder = FrameDecoder::default();
+        assert_eq!(sequences(&mut decoder, &[1, 2, 4, 3, 4, 5, 1500, 1499]), [true, true, true, false, false, true, true, false]);
+        assert_eq!(decoder.lost_frames, 1 + 1494);
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Serialize, Deserialize};
+use hexapod::input_handler::ControllerEvent;
+use hexapod::telemetry::{FootContact, GaitTelemetry, LinkState};
+use crate::topics::{topic_message, TelemetryEvent, Topic, TopicMessage};
+
+//how the telemetry is encoded for a client. JSON is text, the others are binary. Over TCP JSON
+//messages end with a newline and binary ones start with their length as a little-endian u32
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    JSON,
+    CBOR,
+    MSGPACK,
+    BINARY, //the fixed layout of encode_binary
+}
+
+//changes whenever the BINARY layout changes
+pub const BINARY_SCHEMA_VERSION: u8 = 3;
+const BINARY_MODE_SIZE: usize = 16;
+
+pub(crate) struct Encoded {
+    pub encoding: Encoding,
+    pub data: Vec<u8>,
+}
+
+impl Encoding {
+    pub fn is_text(&self) -> bool {
+        *self == Encoding::JSON
+    }
+
+    //FULL messages are the bare TelemetryMessage, the other topics are wrapped in {"<TOPIC>": ...}
+    pub fn encode(&self, msg: &TopicMessage) -> Vec<u8> {
+        match (self, msg) {
+            (Encoding::BINARY, msg) => encode_binary(msg),
+            (encoding, TopicMessage::FULL(msg)) => encoding.encode_serde(msg),
+            (encoding, msg) => encoding.encode_serde(msg),
+        }
+    }
+
+    fn encode_serde<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        match self {
+            Encoding::JSON => serde_json::to_vec(value).unwrap(),
+            Encoding::CBOR => {
+                let mut data = Vec::new();
+                ciborium::into_writer(value, &mut data).unwrap();
+                data
+            },
+            Encoding::MSGPACK => rmp_serde::to_vec_named(value).unwrap(),
+            Encoding::BINARY => unreachable!(),
+        }
+    }
+}
+
+//all numbers are little-endian. Every message starts with
+//  schema version  u8
+//  topic           u8   index in Topic
+//  sequence        u64
+//  timestamp       f64
+//followed by the sections of its topic
+//  FULL            link, mode, gait, input, timing, pose, legs, angles
+//  POSE            pose
+//  ANGLES          angles
+//  LEGS            legs
+//  DIAGNOSTICS     link, mode, gait, input, timing
+//  EVENTS          count u8, then every event as kind u8 (index in TelemetryEvent) and
+//                  MODE_CHANGED the mode, LINK_CHANGED the link, GAIT_CHANGED the gait type,
+//                  LEG_UNREACHABLE/LEG_REACHABLE the leg as u8
+//the sections are
+//  link            u8   0 waiting, 1 connected, 2 lost
+//  mode            16 bytes, utf-8 padded with zeros
+//  gait            type u8 (index in GaitType, 0xFF when the mode doesn't walk), step progress f32,
+//                  stepping feet u8 (bit n is set while leg n is in the air)
+//  input           lx, ly, rx, ry, lt, rt, dpad_x, dpad_y as 8 x f32, then pressed u16 (bit n is
+//                  set while Button n is pressed)
+//  timing          period, busy as 2 x f32
+//  pose            center 2 x f32, rotation f32, body translation 3 x f32, body rotation
+//                  quaternion x, y, z, w as 4 x f32
+//  legs            6 x (joint, hip, knee, foot) as 3 x f32 each
+//  angles          18 x f32, NaN where the leg can't reach its foot, then unreachable u8 (bit n
+//                  is set when leg n can't reach its foot)
+pub fn encode_binary(msg: &TopicMessage) -> Vec<u8> {
+    let mut data = vec![BINARY_SCHEMA_VERSION, msg.topic().id()];
+    let (sequence, timestamp) = msg.stamp();
+    data.extend_from_slice(&sequence.to_le_bytes());
+    data.extend_from_slice(&timestamp.to_le_bytes());
+    match msg {
+        TopicMessage::FULL(full) => {
+            for topic in [Topic::DIAGNOSTICS, Topic::POSE, Topic::LEGS, Topic::ANGLES] {
+                put_section(&mut data, &topic_message(topic, full, &[]).unwrap());
+            }
+        },
+        msg => put_section(&mut data, msg),
+    }
+    data
+}
+
+fn put_section(data: &mut Vec<u8>, msg: &TopicMessage) {
+    match msg {
+        TopicMessage::FULL(_) => unreachable!(),
+        TopicMessage::POSE(pose) => {
+            pose.center.iter().for_each(|v| put_f32(data, *v));
+            put_f32(data, pose.rotation);
+            pose.body.translation.iter().chain(pose.body.rotation.iter()).for_each(|v| put_f32(data, *v));
+        },
+        TopicMessage::ANGLES(angles) => {
+            angles.angles.iter().for_each(|a| put_f32(data, a.unwrap_or(f64::NAN)));
+            data.push(bitmask(angles.unreachable.iter().copied()) as u8);
+        },
+        TopicMessage::LEGS(legs) => {
+            for leg in legs.legs.iter() {
+                [leg.joint, leg.hip, leg.knee, leg.foot].iter().flatten().for_each(|v| put_f32(data, *v));
+            }
+        },
+        TopicMessage::DIAGNOSTICS(diagnostics) => {
+            put_link(data, diagnostics.link);
+            put_mode(data, diagnostics.mode);
+            put_gait(data, diagnostics.gait);
+            put_input(data, diagnostics.input);
+            put_f32(data, diagnostics.timing.period);
+            put_f32(data, diagnostics.timing.busy);
+        },
+        TopicMessage::EVENTS(events) => {
+            data.push(events.events.len().min(u8::MAX as usize) as u8);
+            for event in events.events.iter().take(u8::MAX as usize) {
+                match event {
+                    TelemetryEvent::MODE_CHANGED(mode) => {
+                        data.push(0);
+                        put_mode(data, mode);
+                    },
+                    TelemetryEvent::LINK_CHANGED(link) => {
+                        data.push(1);
+                        put_link(data, *link);
+                    },
+                    TelemetryEvent::GAIT_CHANGED(gait) => data.extend_from_slice(&[2, gait.map_or(0xFF, |g| g as u8)]),
+                    TelemetryEvent::LEG_UNREACHABLE(leg) => data.extend_from_slice(&[3, *leg as u8]),
+                    TelemetryEvent::LEG_REACHABLE(leg) => data.extend_from_slice(&[4, *leg as u8]),
+                }
+            }
+        },
+    }
+}
+
+fn put_f32(data: &mut Vec<u8>, value: f64) {
+    data.extend_from_slice(&(value as f32).to_le_bytes());
+}
+
+fn put_link(data: &mut Vec<u8>, link: LinkState) {
+    data.push(match link {
+        LinkState::WAITING => 0,
+        LinkState::CONNECTED => 1,
+        LinkState::LOST => 2,
+    });
+}
+
+fn put_mode(data: &mut Vec<u8>, mode: &str) {
+    let mut padded = [0u8; BINARY_MODE_SIZE];
+    let name = mode.as_bytes();
+    let len = name.len().min(BINARY_MODE_SIZE);
+    padded[..len].copy_from_slice(&name[..len]);
+    data.extend_from_slice(&padded);
+}
+
+fn put_gait(data: &mut Vec<u8>, gait: &Option<GaitTelemetry>) {
+    match gait {
+        Some(gait) => {
+            data.push(gait.gait as u8);
+            put_f32(data, gait.step_progress);
+            data.push(bitmask(gait.feet.iter().map(|f| *f == FootContact::STEPPING)) as u8);
+        },
+        None => {
+            data.push(0xFF);
+            put_f32(data, 0.0);
+            data.push(0);
+        },
+    }
+}
+
+fn put_input(data: &mut Vec<u8>, input: &ControllerEvent) {
+    for v in [input.lx, input.ly, input.rx, input.ry, input.lt, input.rt, input.dpad_x, input.dpad_y] {
+        put_f32(data, v);
+    }
+    data.extend_from_slice(&(bitmask(input.pressed.iter().copied()) as u16).to_le_bytes());
+}
+
+fn bitmask(bits: impl Iterator<Item = bool>) -> u32 {
+    bits.enumerate().filter(|(_, b)| *b).fold(0, |mask, (i, _)| mask | 1 << i)
+}
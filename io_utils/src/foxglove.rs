@@ -0,0 +1,413 @@
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::io;
+use serde_json::{json, Value};
+use tungstenite::{http, Message, WebSocket};
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use hexapod::hexapod::Foot;
+use hexapod::telemetry::{FootContact, TelemetryMessage};
+use crate::websocket::{accept, accept_clients, serve_client};
+
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+const OP_MESSAGE_DATA: u8 = 0x01;
+const FRAME: &str = "world";
+const MM_TO_M: f64 = 0.001; //the robot works in mm, foxglove in m
+//messages that can wait for a slow client, newer ones are dropped for it while the queue is full
+const CLIENT_QUEUE_SIZE: usize = 16;
+
+//serves the telemetry with the Foxglove WebSocket protocol (https://github.com/foxglove/ws-protocol),
+//so Foxglove Studio can show the robot: open a Foxglove WebSocket connection to ws://<robot>:8768.
+//The channels are json encoded with json schemas, only the ones a client subscribed to are sent
+pub struct FoxgloveServer {
+    new_clients: Receiver<FoxgloveClient>,
+    clients: Vec<FoxgloveClient>,
+    interval: Duration, //between two published messages
+    last_publish: Instant,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+enum Channel {
+    POSE, //foxglove.PoseInFrame of the body
+    JOINT_STATES, //the servo angles by joint name
+    SCENE, //foxglove.SceneUpdate with the body, leg segments and feet
+    FOOT_CONTACT, //which feet are on the ground
+}
+
+struct FoxgloveClient {
+    addr: SocketAddr,
+    subscriptions: Arc<Mutex<Vec<(u32, Channel)>>>, //subscription ids chosen by the client
+    queue: SyncSender<(u32, Arc<ChannelMessage>)>,
+}
+
+struct ChannelMessage {
+    timestamp: u64, //ns since the unix epoch
+    payload: Vec<u8>,
+}
+
+impl FoxgloveServer {
+    //publishes at most publish_rate messages per second, the telemetry in between is skipped
+    pub fn new(addr: &str, publish_rate: f64) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("foxglove on {}", listener.local_addr()?);
+
+        let (client_sender, new_clients) = mpsc::channel();
+        accept_clients(listener, "foxglove", move |stream, addr| run_client(stream, addr, client_sender.clone()));
+
+        Ok(Self {
+            new_clients,
+            clients: Vec::new(),
+            interval: Duration::from_secs_f64(1.0 / publish_rate),
+            last_publish: Instant::now(),
+        })
+    }
+
+    pub fn publish(&mut self, msg: &TelemetryMessage) {
+        while let Ok(client) = self.new_clients.try_recv() {
+            println!("foxglove client {} connected", client.addr);
+            self.clients.push(client);
+        }
+
+        if self.last_publish.elapsed() < self.interval {
+            return;
+        }
+        self.last_publish = Instant::now();
+
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        //every channel is only encoded once, and only when someone subscribed to it
+        let mut encoded: Vec<(Channel, Arc<ChannelMessage>)> = Vec::new();
+        self.clients.retain_mut(|client| {
+            let subscriptions = client.subscriptions.lock().unwrap().clone();
+            for (id, channel) in subscriptions {
+                let message = match encoded.iter().find(|(c, _)| *c == channel) {
+                    Some((_, message)) => message.clone(),
+                    None => {
+                        let payload = serde_json::to_vec(&channel.message(msg, time)).unwrap();
+                        let message = Arc::new(ChannelMessage { timestamp: time.as_nanos() as u64, payload });
+                        encoded.push((channel, message.clone()));
+                        message
+                    },
+                };
+                match client.queue.try_send((id, message)) {
+                    Ok(()) | Err(TrySendError::Full(_)) => {},
+                    Err(TrySendError::Disconnected(_)) => {
+                        println!("foxglove client {} disconnected", client.addr);
+                        return false;
+                    },
+                }
+            }
+            true
+        });
+    }
+}
+
+impl Channel {
+    fn all() -> [Channel; 4] {
+        use Channel::*;
+        [POSE, JOINT_STATES, SCENE, FOOT_CONTACT]
+    }
+
+    //the ids start at 1
+    fn id(&self) -> u32 {
+        *self as u32 + 1
+    }
+
+    fn from_id(id: u64) -> Option<Channel> {
+        Self::all().iter().copied().find(|c| c.id() as u64 == id)
+    }
+
+    fn topic(&self) -> &'static str {
+        match self {
+            Channel::POSE => "/hexapod/pose",
+            Channel::JOINT_STATES => "/hexapod/joint_states",
+            Channel::SCENE => "/hexapod/scene",
+            Channel::FOOT_CONTACT => "/hexapod/foot_contact",
+        }
+    }
+
+    fn schema_name(&self) -> &'static str {
+        match self {
+            Channel::POSE => "foxglove.PoseInFrame",
+            Channel::JOINT_STATES => "hexapod.JointStates",
+            Channel::SCENE => "foxglove.SceneUpdate",
+            Channel::FOOT_CONTACT => "hexapod.FootContacts",
+        }
+    }
+
+    fn advertisement(&self) -> Value {
+        json!({
+            "id": self.id(),
+            "topic": self.topic(),
+            "encoding": "json",
+            "schemaName": self.schema_name(),
+            "schema": self.schema().to_string(),
+            "schemaEncoding": "jsonschema",
+        })
+    }
+
+    fn schema(&self) -> Value {
+        let number = json!({ "type": "number" });
+        let time = json!({ "type": "object", "properties": { "sec": { "type": "integer" }, "nsec": { "type": "integer" } } });
+        let vector = json!({ "type": "object", "properties": { "x": number, "y": number, "z": number } });
+        let quaternion = json!({ "type": "object", "properties": { "x": number, "y": number, "z": number, "w": number } });
+        let pose = json!({ "type": "object", "properties": { "position": vector, "orientation": quaternion } });
+        let color = json!({ "type": "object", "properties": { "r": number, "g": number, "b": number, "a": number } });
+        let array = |items: Value| json!({ "type": "array", "items": items });
+        let object = |properties: Value| json!({ "type": "object", "properties": properties });
+        match self {
+            Channel::POSE => object(json!({ "timestamp": time, "frame_id": { "type": "string" }, "pose": pose })),
+            Channel::JOINT_STATES => object(json!({
+                "timestamp": time,
+                "name": array(json!({ "type": "string" })),
+                "position": array(json!({ "type": ["number", "null"] })), //null when the leg can't reach its foot
+            })),
+            Channel::SCENE => object(json!({
+                "deletions": array(object(json!({ "timestamp": time, "type": { "type": "integer" }, "id": { "type": "string" } }))),
+                "entities": array(object(json!({
+                    "timestamp": time,
+                    "frame_id": { "type": "string" },
+                    "id": { "type": "string" },
+                    "lifetime": time,
+                    "frame_locked": { "type": "boolean" },
+                    "lines": array(object(json!({
+                        "type": { "type": "integer" },
+                        "pose": pose,
+                        "thickness": number,
+                        "scale_invariant": { "type": "boolean" },
+                        "points": array(vector.clone()),
+                        "color": color,
+                    }))),
+                    "spheres": array(object(json!({ "pose": pose, "size": vector, "color": color }))),
+                }))),
+            })),
+            Channel::FOOT_CONTACT => object(json!({
+                "timestamp": time,
+                "feet": array(object(json!({
+                    "leg": { "type": "string" },
+                    "contact": { "type": "boolean" },
+                    "position": vector,
+                }))),
+            })),
+        }
+    }
+
+    fn message(&self, msg: &TelemetryMessage, time: Duration) -> Value {
+        let timestamp = json!({ "sec": time.as_secs(), "nsec": time.subsec_nanos() });
+        let contacts = contacts(msg);
+        match self {
+            Channel::POSE => json!({ "timestamp": timestamp, "frame_id": FRAME, "pose": body_pose(msg) }),
+            Channel::JOINT_STATES => json!({
+                "timestamp": timestamp,
+                "name": Foot::all().iter().flat_map(|f| f.joint_names()).collect::<Vec<_>>(),
+                "position": msg.angles,
+            }),
+            Channel::SCENE => {
+                let entity = |id: &str, lines: Vec<Value>, spheres: Vec<Value>| json!({
+                    "timestamp": timestamp,
+                    "frame_id": FRAME,
+                    "id": id,
+                    "lifetime": { "sec": 0, "nsec": 0 },
+                    "frame_locked": false,
+                    "lines": lines,
+                    "spheres": spheres,
+                });
+                let body = msg.legs.iter().map(|leg| point(leg.joint)).collect::<Vec<_>>();
+                let legs = msg.legs.iter().map(|leg| {
+                    let points = [leg.joint, leg.hip, leg.knee, leg.foot].map(point);
+                    line(0, points.to_vec(), [0.2, 0.6, 1.0])
+                }).collect();
+                let feet = msg.legs.iter().zip(contacts.iter()).map(|(leg, contact)| {
+                    let color = if *contact { [0.2, 0.8, 0.2] } else { [1.0, 0.5, 0.0] };
+                    json!({
+                        "pose": { "position": point(leg.foot), "orientation": { "x": 0, "y": 0, "z": 0, "w": 1 } },
+                        "size": { "x": 0.015, "y": 0.015, "z": 0.015 },
+                        "color": rgba(color),
+                    })
+                }).collect();
+                json!({
+                    "deletions": [],
+                    "entities": [
+                        entity("body", vec![line(1, body, [0.8, 0.8, 0.8])], vec![]),
+                        entity("legs", legs, vec![]),
+                        entity("feet", vec![], feet),
+                    ],
+                })
+            },
+            Channel::FOOT_CONTACT => json!({
+                "timestamp": timestamp,
+                "feet": Foot::all().iter().zip(msg.legs.iter()).zip(contacts.iter()).map(|((foot, leg), contact)| json!({
+                    "leg": format!("{:?}", foot).to_lowercase(),
+                    "contact": contact,
+                    "position": point(leg.foot),
+                })).collect::<Vec<_>>(),
+            }),
+        }
+    }
+}
+
+//a foot is on the ground unless the gait lifts it, modes without a gait don't lift any feet
+fn contacts(msg: &TelemetryMessage) -> [bool; 6] {
+    match &msg.gait {
+        Some(gait) => gait.feet.map(|f| f == FootContact::STANDING),
+        None => [true; 6],
+    }
+}
+
+fn point(p: [f64; 3]) -> Value {
+    json!({ "x": p[0] * MM_TO_M, "y": p[1] * MM_TO_M, "z": p[2] * MM_TO_M })
+}
+
+fn rgba(color: [f64; 3]) -> Value {
+    json!({ "r": color[0], "g": color[1], "b": color[2], "a": 1.0 })
+}
+
+//type 0 is a line strip, 1 a closed loop
+fn line(kind: u8, points: Vec<Value>, color: [f64; 3]) -> Value {
+    json!({
+        "type": kind,
+        "pose": { "position": { "x": 0, "y": 0, "z": 0 }, "orientation": { "x": 0, "y": 0, "z": 0, "w": 1 } },
+        "thickness": 4.0,
+        "scale_invariant": true,
+        "points": points,
+        "color": rgba(color),
+    })
+}
+
+//msg.center and msg.rotation already contain the bodyTransform, only its height and its tilt are
+//missing. msg.rotation is the heading of the body, which is the yaw of the origin plus the one of
+//the body rotation
+fn body_pose(msg: &TelemetryMessage) -> Value {
+    let [x, y, z, w] = msg.body.rotation;
+    let body_yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+    //yaw quaternion (0, 0, sin(yaw/2), cos(yaw/2)) of the origin times the body rotation
+    let (sin, cos) = ((msg.rotation - body_yaw) / 2.0).sin_cos();
+    json!({
+        "position": point([msg.center[0], msg.center[1], msg.body.translation[2]]),
+        "orientation": {
+            "x": cos * x - sin * y,
+            "y": cos * y + sin * x,
+            "z": cos * z + sin * w,
+            "w": cos * w - sin * z,
+        },
+    })
+}
+
+//sends the server info and the channels, then the messages of the subscribed channels. The
+//client's subscribe and unsubscribe requests change what the server sends it
+fn run_client(stream: TcpStream, addr: SocketAddr, clients: Sender<FoxgloveClient>) {
+    #[allow(clippy::result_large_err)] //the signature is given by tungstenite
+    let choose_subprotocol = |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        let offered = request.headers().get_all(http::header::SEC_WEBSOCKET_PROTOCOL).iter()
+            .filter_map(|v| v.to_str().ok())
+            .any(|v| v.split(',').any(|p| p.trim() == SUBPROTOCOL));
+        if offered {
+            response.headers_mut().insert(http::header::SEC_WEBSOCKET_PROTOCOL, http::HeaderValue::from_static(SUBPROTOCOL));
+        }
+        Ok(response)
+    };
+    let mut socket = match accept(stream, addr, "foxglove", choose_subprotocol) {
+        Some(socket) => socket,
+        None => return,
+    };
+    let server_info = json!({
+        "op": "serverInfo",
+        "name": "hexapod",
+        "capabilities": [],
+        "supportedEncodings": ["json"],
+        "metadata": {},
+    });
+    let advertise = json!({ "op": "advertise", "channels": Channel::all().map(|c| c.advertisement()) });
+    for message in [server_info, advertise].iter() {
+        if let Err(e) = socket.send(Message::Text(message.to_string())) {
+            eprintln!("foxglove client {} failed: {}", addr, e);
+            return;
+        }
+    }
+
+    let subscriptions = Arc::new(Mutex::new(Vec::new()));
+    let (tx, messages) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+    let client = FoxgloveClient { addr, subscriptions: subscriptions.clone(), queue: tx };
+    if clients.send(client).is_err() {
+        return;
+    }
+
+    serve_client(&mut socket, |socket| send_messages(socket, &messages), |_, text| {
+        match serde_json::from_str::<Value>(&text) {
+            Ok(request) => handle_request(&request, &subscriptions),
+            Err(e) => eprintln!("foxglove client {} sent invalid json: {}", addr, e),
+        }
+        Ok(())
+    });
+}
+
+//only subscribe and unsubscribe are supported, the server doesn't advertise any other capabilities
+fn handle_request(request: &Value, subscriptions: &Mutex<Vec<(u32, Channel)>>) {
+    let mut subscriptions = subscriptions.lock().unwrap();
+    match request["op"].as_str() {
+        Some("subscribe") => {
+            for subscription in request["subscriptions"].as_array().into_iter().flatten() {
+                let id = subscription["id"].as_u64();
+                let channel = subscription["channelId"].as_u64().and_then(Channel::from_id);
+                if let (Some(id), Some(channel)) = (id, channel) {
+                    subscriptions.retain(|(i, _)| *i as u64 != id);
+                    subscriptions.push((id as u32, channel));
+                }
+            }
+        },
+        Some("unsubscribe") => {
+            let ids = request["subscriptionIds"].as_array().into_iter().flatten().filter_map(|id| id.as_u64()).collect::<Vec<_>>();
+            subscriptions.retain(|(id, _)| !ids.contains(&(*id as u64)));
+        },
+        _ => {},
+    }
+}
+
+//fails once the server has dropped the client or the client doesn't take the data
+fn send_messages(socket: &mut WebSocket<TcpStream>, messages: &Receiver<(u32, Arc<ChannelMessage>)>) -> Result<(), ()> {
+    loop {
+        match messages.try_recv() {
+            Ok((subscription, message)) => {
+                let mut frame = Vec::with_capacity(1 + 4 + 8 + message.payload.len());
+                frame.push(OP_MESSAGE_DATA);
+                frame.extend_from_slice(&subscription.to_le_bytes());
+                frame.extend_from_slice(&message.timestamp.to_le_bytes());
+                frame.extend_from_slice(&message.payload);
+                socket.send(Message::Binary(frame)).map_err(|_| ())?
+            },
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Disconnected) => return Err(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexapod::{Isometry2, Rotation2, Vector2};
+    use hexapod::body_pose::BodyPose;
+    use hexapod::hexapod::Hexapod;
+
+    #[test]
+    fn publishes_the_body_transform_once() {
+        let mut hexapod = Hexapod::new();
+        hexapod.origin = Isometry2::new(Vector2::new(50.0, -30.0), Rotation2::radians(0.5));
+        hexapod.bodyTransform = BodyPose::new(20.0, 10.0, 0.0, 0.0, 0.0, 0.3).to_isometry(100.0);
+        let pose = body_pose(&hexapod.get_telemetry());
+
+        let value = |v: &Value| v.as_f64().unwrap();
+        let (sin, cos) = 0.5f64.sin_cos();
+        let expected = [50.0 + cos * 20.0 - sin * 10.0, -30.0 + sin * 20.0 + cos * 10.0, 100.0];
+        for (axis, expected) in ["x", "y", "z"].iter().zip(expected.iter()) {
+            assert!((value(&pose["position"][axis]) - expected * MM_TO_M).abs() < 1e-9, "{} of {}", axis, pose);
+        }
+        //msg.rotation comes from euclid's approximated atan2
+        let (sin, cos) = (0.8f64 / 2.0).sin_cos();
+        let expected = [0.0, 0.0, sin, cos];
+        for (axis, expected) in ["x", "y", "z", "w"].iter().zip(expected.iter()) {
+            assert!((value(&pose["orientation"][axis]) - expected).abs() < 1e-4, "{} of {}", axis, pose);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::io;
+use std::str::FromStr;
+use evdev::{AbsoluteAxisCode, Device, KeyCode};
+use hexapod::config::GamepadMapping;
+use hexapod::input_handler::{Button, ControllerEvent};
+use crate::input::{next_timestamp, InputSource};
+
+//an axis that is used as a button is pressed beyond this fraction of its range
+const AXIS_BUTTON_THRESHOLD: f64 = 0.5;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Axis {
+    pub code: AbsoluteAxisCode,
+    pub invert: bool,
+}
+
+impl Axis {
+    //"ABS_X", "-ABS_X" or "+ABS_X"
+    pub fn parse(name: &str) -> Result<Self, Box<dyn Error>> {
+        let (invert, code) = match name.strip_prefix('-') {
+            Some(code) => (true, code),
+            None => (false, name.strip_prefix('+').unwrap_or(name)),
+        };
+        let code = AbsoluteAxisCode::from_str(code).map_err(|_| format!("unknown axis: {}", name))?;
+        Ok(Self { code, invert })
+    }
+
+    //the position from 0 to 1, None if the device does not have the axis
+    fn fraction(&self, device: &Device) -> Option<f64> {
+        let info = device.cached_state().abs_vals()?[self.code.0 as usize];
+        if info.maximum <= info.minimum {
+            return None;
+        }
+        let fraction = (info.value - info.minimum) as f64 / (info.maximum - info.minimum) as f64;
+        Some(if self.invert { 1.0 - fraction } else { fraction })
+    }
+
+    //sticks go from -1 to 1
+    fn value(&self, device: &Device) -> f64 {
+        self.fraction(device).map_or(0.0, |f| f * 2.0 - 1.0)
+    }
+}
+
+//conf.gamepad with the names resolved to evdev codes
+#[derive(Debug, Clone)]
+pub struct ParsedMapping {
+    pub sticks: [Axis; 4], //lx, ly, rx, ry
+    pub triggers: [Option<Axis>; 2],
+    pub dpad: [Option<Axis>; 2],
+    pub keys: Vec<(KeyCode, Button)>,
+    pub axis_buttons: Vec<(Axis, Button)>,
+}
+
+impl ParsedMapping {
+    pub fn parse(mapping: &GamepadMapping) -> Result<Self, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        let mut axis_buttons = Vec::new();
+        for (name, button) in mapping.buttons.iter() {
+            match KeyCode::from_str(name) {
+                Ok(code) => keys.push((code, *button)),
+                Err(_) => {
+                    let axis = Axis::parse(name).map_err(|_| format!("unknown key or axis: {}", name))?;
+                    axis_buttons.push((axis, *button));
+                },
+            }
+        }
+
+        Ok(Self {
+            sticks: [
+                Axis::parse(&mapping.lx)?, Axis::parse(&mapping.ly)?,
+                Axis::parse(&mapping.rx)?, Axis::parse(&mapping.ry)?,
+            ],
+            triggers: [parse_optional(&mapping.lt)?, parse_optional(&mapping.rt)?],
+            dpad: [parse_optional(&mapping.dpad_x)?, parse_optional(&mapping.dpad_y)?],
+            keys,
+            axis_buttons,
+        })
+    }
+}
+
+//reads a gamepad that is connected to this machine, mapped with conf.gamepad. Without a path the
+//first device that has all the mapped stick axes is used, so a bluetooth controller is found again
+//after it reconnected under a different path
+pub struct GamepadSource {
+    path: Option<String>,
+    mapping: ParsedMapping,
+    device: Option<Device>,
+    pressed: [bool; 12],
+}
+
+impl GamepadSource {
+    pub fn new(path: Option<&str>, mapping: &GamepadMapping) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            path: path.map(|p| p.to_string()),
+            mapping: ParsedMapping::parse(mapping)?,
+            device: None,
+            pressed: [false; 12],
+        })
+    }
+
+    fn has_sticks(&self, device: &Device) -> bool {
+        device.supported_absolute_axes().is_some_and(|axes| self.mapping.sticks.iter().all(|s| axes.contains(s.code)))
+    }
+
+    fn event(&mut self) -> ControllerEvent {
+        let device = self.device.as_ref().unwrap();
+        let mut pressed = [false; 12];
+        if let Some(keys) = device.cached_state().key_vals() {
+            for (code, button) in self.mapping.keys.iter() {
+                pressed[*button as usize] |= keys.contains(*code);
+            }
+        }
+        for (axis, button) in self.mapping.axis_buttons.iter() {
+            pressed[*button as usize] |= axis.value(device) > AXIS_BUTTON_THRESHOLD;
+        }
+
+        let mut triggered = [false; 12];
+        for i in 0..12 {
+            triggered[i] = pressed[i] && !self.pressed[i];
+        }
+        self.pressed = pressed;
+
+        let [lx, ly, rx, ry] = self.mapping.sticks.map(|s| s.value(device));
+        let [lt, rt] = self.mapping.triggers.map(|t| t.and_then(|t| t.fraction(device)).unwrap_or(0.0));
+        let [dpad_x, dpad_y] = self.mapping.dpad.map(|d| d.map_or(0.0, |d| d.value(device)));
+        ControllerEvent { timestamp: next_timestamp(), lx, ly, rx, ry, pressed, triggered, lt, rt, dpad_x, dpad_y }
+    }
+}
+
+fn parse_optional(name: &Option<String>) -> Result<Option<Axis>, Box<dyn Error>> {
+    name.as_deref().map(Axis::parse).transpose()
+}
+
+impl InputSource for GamepadSource {
+    fn connect(&mut self) -> io::Result<()> {
+        let device = match &self.path {
+            Some(path) => Device::open(path)?,
+            None => evdev::enumerate()
+                .map(|(_, device)| device)
+                .find(|device| self.has_sticks(device))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no gamepad found"))?,
+        };
+        println!("using gamepad {}", device.name().unwrap_or("unnamed"));
+        self.device = Some(device);
+        self.pressed = [false; 12];
+        Ok(())
+    }
+
+    //one event per report of the device, i.e. after every change
+    fn receive(&mut self) -> io::Result<ControllerEvent> {
+        let device = self.device.as_mut().ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        device.fetch_events()?.for_each(drop);
+        Ok(self.event())
+    }
+
+    fn description(&self) -> String {
+        format!("gamepad {}", self.path.as_deref().unwrap_or("(any)"))
+    }
+}
@@ -0,0 +1,91 @@
+use std::error::Error;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+use hexapod::config::Config;
+use hexapod::input_handler::ControllerEvent;
+
+pub mod tcp;
+pub mod udp;
+pub mod stdin;
+pub mod gamepad;
+
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+//somewhere controller events come from
+pub trait InputSource: Send {
+    //(re)establishes the connection and blocks until a controller is available.
+    //An UnexpectedEof error means the source is exhausted and will not come back
+    fn connect(&mut self) -> io::Result<()>;
+    //blocks until the next event arrives, an error means the connection was lost
+    fn receive(&mut self) -> io::Result<ControllerEvent>;
+    fn description(&self) -> String;
+}
+
+//timestamps are unique across all sources and reconnects, otherwise the InputHandler
+//would ignore the buttons triggered in the first event after a reconnect
+pub fn next_timestamp() -> usize {
+    static TIMESTAMP: AtomicUsize = AtomicUsize::new(1);
+    TIMESTAMP.fetch_add(1, Ordering::Relaxed)
+}
+
+//creates a source from a spec like "tcp-client:host:port", "tcp-server:0.0.0.0:8765",
+//"udp:0.0.0.0:8765", "stdin", "evdev" (first gamepad found) or "evdev:/dev/input/event0". The
+//spec is conf.input or --input on the robot
+pub fn parse_source(spec: &str, conf: &Config) -> Result<Box<dyn InputSource>, Box<dyn Error>> {
+    let (kind, addr) = spec.split_once(':').unwrap_or((spec, ""));
+    let timeout = Duration::from_secs_f64(conf.controller_timeout);
+    let source: Box<dyn InputSource> = match kind {
+        "tcp-client" => Box::new(tcp::TcpClientSource::new(addr, conf.controller_protocol, timeout)),
+        "tcp-server" => Box::new(tcp::TcpServerSource::new(addr, conf.controller_protocol, timeout)),
+        "udp" => Box::new(udp::UdpSource::new(addr, conf.controller_protocol)),
+        "stdin" => Box::new(stdin::StdinSource::new()),
+        "evdev" => Box::new(gamepad::GamepadSource::new(Some(addr).filter(|a| !a.is_empty()), &conf.gamepad)?),
+        _ => return Err(format!("unknown input source: {}", spec).into()),
+    };
+    if !matches!(kind, "stdin" | "evdev") && addr.is_empty() {
+        return Err(format!("input source {} needs an address", kind).into());
+    }
+    Ok(source)
+}
+
+pub fn input_thread_inner(mut source: Box<dyn InputSource>, sender: Sender<ControllerEvent>) {
+    loop {
+        match source.connect() {
+            Ok(()) => println!("{} connected", source.description()),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                println!("{} closed", source.description());
+                return;
+            },
+            Err(e) => {
+                eprintln!("{}: {}, retrying", source.description(), e);
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            },
+        }
+
+        loop {
+            match source.receive() {
+                Ok(event) => if sender.send(event).is_err() {
+                    return;
+                },
+                Err(e) => {
+                    eprintln!("{} disconnected: {}", source.description(), e);
+                    break;
+                },
+            }
+        }
+    }
+}
+
+//receives the events in the background and keeps reconnecting when the connection drops
+pub fn start_input_thread(source: Box<dyn InputSource>) -> Receiver<ControllerEvent> {
+    let (tx, rx): (Sender<ControllerEvent>, Receiver<ControllerEvent>) = mpsc::channel();
+    thread::spawn(move || {
+        input_thread_inner(source, tx);
+    });
+
+    rx
+}
@@ -0,0 +1,104 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use hexapod::from_name;
+use hexapod::input_handler::{Button, ControllerEvent};
+use crate::input::{next_timestamp, InputSource};
+
+//the current state is repeated this often while no line is typed, so the watchdog does not
+//consider the link lost
+const REPEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+//keyboard teleop, every line typed replaces the whole controller state. A line is a list of
+//tokens separated by spaces:
+//  w/s, a/d, q/e     left stick forward/back, left/right and the right stick x axis (turning)
+//  lx=0.5 ry=-1 ...  sets a stick axis
+//  A, ZR, UP, ...    presses the button (in upper case) until the next line
+//an empty line releases everything
+pub struct StdinSource {
+    lines: Option<Receiver<String>>,
+    event: ControllerEvent,
+}
+
+impl StdinSource {
+    pub fn new() -> Self {
+        Self {
+            lines: None,
+            event: ControllerEvent::default(),
+        }
+    }
+}
+
+impl Default for StdinSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputSource for StdinSource {
+    fn connect(&mut self) -> io::Result<()> {
+        if self.lines.is_some() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin closed"));
+        }
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                if line.map(|l| tx.send(l)).is_err() {
+                    break;
+                }
+            }
+        });
+        self.lines = Some(rx);
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ControllerEvent> {
+        let lines = self.lines.as_ref().ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        match lines.recv_timeout(REPEAT_INTERVAL) {
+            Ok(line) => {
+                self.event = parse_line(&line);
+                self.event.timestamp = next_timestamp();
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "stdin closed")),
+        }
+        Ok(self.event)
+    }
+
+    fn description(&self) -> String {
+        String::from("stdin")
+    }
+}
+
+fn parse_line(line: &str) -> ControllerEvent {
+    let mut event = ControllerEvent::default();
+    for token in line.split_whitespace() {
+        match token {
+            "w" => event.ly = 1.0,
+            "s" => event.ly = -1.0,
+            "a" => event.lx = -1.0,
+            "d" => event.lx = 1.0,
+            "q" => event.rx = -1.0,
+            "e" => event.rx = 1.0,
+            _ => {
+                if let Some((axis, value)) = token.split_once('=') {
+                    let value = value.parse::<f64>().unwrap_or(0.0).clamp(-1.0, 1.0);
+                    match axis {
+                        "lx" => event.lx = value,
+                        "ly" => event.ly = value,
+                        "rx" => event.rx = value,
+                        "ry" => event.ry = value,
+                        _ => eprintln!("unknown axis: {}", axis),
+                    }
+                } else if let Some(button) = from_name::<Button>(token) {
+                    event.pressed[button as usize] = true;
+                    event.triggered[button as usize] = true;
+                } else {
+                    eprintln!("unknown input: {}", token);
+                }
+            },
+        }
+    }
+    event
+}
@@ -0,0 +1,140 @@
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use hexapod::config::ControllerProtocol;
+use hexapod::input_handler::ControllerEvent;
+use crate::controller::MessageReader;
+use crate::input::{next_timestamp, InputSource};
+
+//connects to a controller that listens on the given address
+pub struct TcpClientSource {
+    addr: String,
+    protocol: ControllerProtocol,
+    timeout: Duration, //without an event for this long the connection is dropped
+    connection: Option<(TcpStream, MessageReader)>,
+}
+
+impl TcpClientSource {
+    pub fn new(addr: &str, protocol: ControllerProtocol, timeout: Duration) -> Self {
+        Self {
+            addr: addr.to_string(),
+            protocol,
+            timeout,
+            connection: None,
+        }
+    }
+}
+
+impl InputSource for TcpClientSource {
+    fn connect(&mut self) -> io::Result<()> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        self.connection = Some((stream, MessageReader::new(self.protocol)));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ControllerEvent> {
+        read_event(&mut self.connection)
+    }
+
+    fn description(&self) -> String {
+        format!("tcp controller {}", self.addr)
+    }
+}
+
+//waits for a controller to connect to the given address, one controller at a time
+pub struct TcpServerSource {
+    addr: String,
+    protocol: ControllerProtocol,
+    timeout: Duration,
+    listener: Option<TcpListener>,
+    connection: Option<(TcpStream, MessageReader)>,
+}
+
+impl TcpServerSource {
+    pub fn new(addr: &str, protocol: ControllerProtocol, timeout: Duration) -> Self {
+        Self {
+            addr: addr.to_string(),
+            protocol,
+            timeout,
+            listener: None,
+            connection: None,
+        }
+    }
+}
+
+impl InputSource for TcpServerSource {
+    fn connect(&mut self) -> io::Result<()> {
+        if self.listener.is_none() {
+            self.listener = Some(TcpListener::bind(&self.addr)?);
+        }
+        let (stream, addr) = self.listener.as_ref().unwrap().accept()?;
+        println!("controller {} dialed in", addr);
+        stream.set_read_timeout(Some(self.timeout))?;
+        self.connection = Some((stream, MessageReader::new(self.protocol)));
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ControllerEvent> {
+        read_event(&mut self.connection)
+    }
+
+    fn description(&self) -> String {
+        format!("tcp server {}", self.addr)
+    }
+}
+
+//a controller that stops sending without closing the connection (e.g. out of range or powered
+//off) is dropped like a closed one after the read timeout, the input thread then reconnects
+fn read_event(connection: &mut Option<(TcpStream, MessageReader)>) -> io::Result<ControllerEvent> {
+    let (stream, reader) = connection.as_mut().ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+    match reader.read_event(stream) {
+        Ok(event) => Ok(ControllerEvent { timestamp: next_timestamp(), ..event }),
+        Err(e) => {
+            *connection = None;
+            match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => Err(io::Error::new(io::ErrorKind::TimedOut, "no events within the controller timeout")),
+                _ => Err(e),
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::thread;
+    use std::time::Instant;
+    use crate::controller::encode_frame;
+
+    #[test]
+    fn drops_a_controller_that_stops_sending() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut source = TcpClientSource::new(&addr, ControllerProtocol::FRAMED, Duration::from_millis(100));
+
+        //sends one event and then goes quiet without closing the connection
+        let peer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(&encode_frame(&ControllerEvent { ly: 1.0, ..Default::default() }, 1)).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let closed = stream.read(&mut [0u8; 16]).unwrap() == 0;
+            (closed, listener)
+        });
+
+        source.connect().unwrap();
+        assert_eq!(source.receive().unwrap().ly, 1.0);
+        let start = Instant::now();
+        assert_eq!(source.receive().unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let (closed, listener) = peer.join().unwrap();
+        assert!(closed, "the connection wasn't dropped");
+        assert_eq!(source.receive().unwrap_err().kind(), io::ErrorKind::NotConnected);
+
+        //the input thread reconnects
+        source.connect().unwrap();
+        listener.accept().unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use hexapod::config::ControllerProtocol;
+use hexapod::input_handler::ControllerEvent;
+use crate::controller::{MessageReader, HEADER_SIZE, CRC_SIZE, MAX_PAYLOAD_SIZE};
+use crate::input::{next_timestamp, InputSource};
+
+//receives one message or frame per datagram on the given address, broken datagrams are dropped.
+//There is no connection, a controller that stops sending is noticed by the watchdog. Every sender
+//has its own sequence numbers, so a second controller or a restarted one isn't taken for old frames
+pub struct UdpSource {
+    addr: String,
+    protocol: ControllerProtocol,
+    socket: Option<UdpSocket>,
+    readers: HashMap<SocketAddr, MessageReader>,
+}
+
+impl UdpSource {
+    pub fn new(addr: &str, protocol: ControllerProtocol) -> Self {
+        Self {
+            addr: addr.to_string(),
+            protocol,
+            socket: None,
+            readers: HashMap::new(),
+        }
+    }
+}
+
+impl InputSource for UdpSource {
+    fn connect(&mut self) -> io::Result<()> {
+        if self.socket.is_none() {
+            self.socket = Some(UdpSocket::bind(&self.addr)?);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<ControllerEvent> {
+        let socket = self.socket.as_ref().ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected))?;
+        let mut buf = [0u8; HEADER_SIZE + MAX_PAYLOAD_SIZE + CRC_SIZE];
+        loop {
+            let (len, addr) = socket.recv_from(&mut buf)?;
+            let protocol = self.protocol;
+            let reader = self.readers.entry(addr).or_insert_with(|| MessageReader::new(protocol));
+            if let Some(event) = reader.decode_datagram(&buf[..len]) {
+                return Ok(ControllerEvent { timestamp: next_timestamp(), ..event });
+            }
+        }
+    }
+
+    fn description(&self) -> String {
+        format!("udp {}", self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::encode_frame;
+
+    #[test]
+    fn keeps_the_sequence_of_every_sender() {
+        //the source binds the address itself
+        let addr = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut source = UdpSource::new(&addr.to_string(), ControllerProtocol::FRAMED);
+        source.connect().unwrap();
+
+        let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let second = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send = |socket: &UdpSocket, ly: f64, sequence: u32| {
+            socket.send_to(&encode_frame(&ControllerEvent { ly, ..Default::default() }, sequence), addr).unwrap();
+        };
+        send(&first, 1.0, 500);
+        send(&second, -1.0, 1);
+        send(&first, 0.0, 501);
+        send(&second, 1.0, 2);
+
+        let received: Vec<f64> = (0..4).map(|_| source.receive().unwrap().ly).collect();
+        assert_eq!(received, [1.0, -1.0, 0.0, 1.0]);
+    }
+}
@@ -1,2 +1,11 @@
+pub mod args;
 pub mod controller;
-pub mod telemetry;
\ No newline at end of file
+pub mod encoding;
+pub mod foxglove;
+pub mod input;
+pub mod telemetry;
+pub mod telemetry_log;
+pub mod topics;
+pub mod recording;
+pub mod watchdog;
+pub mod websocket;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use hexapod::config::Config;
+use hexapod::hexapod::Hexapod;
+use hexapod::input_handler::{ControllerEvent, InputHandler};
+use hexapod::telemetry::TelemetryMessage;
+
+//one line of a recording, the time is relative to the start of the recording
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordedEvent {
+    pub time_ms: u64,
+    pub event: ControllerEvent,
+}
+
+//writes every controller event as a json line
+pub struct SessionRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl SessionRecorder {
+    pub fn new(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, event: &ControllerEvent) -> io::Result<()> {
+        let recorded = RecordedEvent {
+            time_ms: self.start.elapsed().as_millis() as u64,
+            event: *event,
+        };
+        let line = serde_json::to_string(&recorded)? + "\n";
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+pub fn load_session(path: &str) -> io::Result<Vec<RecordedEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(events)
+}
+
+//runs the control loop over a recording without the robot and returns the telemetry of every
+//tick. Like on the robot the events that arrived since the last tick are merged into one, the
+//ticks are conf.tick_period apart. Everything runs on the tick clock, so the same recording and
+//config always give the same result
+pub fn replay_session(events: Vec<RecordedEvent>, mut conf: Config) -> Vec<TelemetryMessage> {
+    let mut hexapod = Hexapod::new();
+    let mut input_handler = InputHandler::new(&conf);
+    let mut last_event = ControllerEvent::default();
+    let mut telemetry = Vec::new();
+
+    let mut pending = events.into_iter().peekable();
+    for tick in 0.. {
+        let time_ms = tick as f64 * conf.tick_period * 1000.0;
+        let mut due = Vec::new();
+        while let Some(recorded) = pending.next_if(|e| e.time_ms as f64 <= time_ms) {
+            due.push(recorded.event);
+        }
+        match ControllerEvent::merge(&due) {
+            Some(event) => last_event = event,
+            None if pending.peek().is_none() => break,
+            None => {},
+        }
+
+        input_handler.handle_input(last_event, &mut hexapod, &mut conf);
+        telemetry.push(input_handler.get_telemetry(&mut hexapod));
+    }
+    telemetry
+}
+
+pub fn replay_thread_inner(events: Vec<RecordedEvent>, sender: Sender<ControllerEvent>) {
+    let start = Instant::now();
+    for recorded in events {
+        let time = Duration::from_millis(recorded.time_ms);
+        if let Some(remaining) = time.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+        if sender.send(recorded.event).is_err() {
+            break;
+        }
+    }
+}
+
+//sends the recorded events with their original timing, like start_input_thread does for live input
+pub fn start_replay_thread(path: &str) -> io::Result<Receiver<ControllerEvent>> {
+    let events = load_session(path)?;
+    let (tx, rx): (Sender<ControllerEvent>, Receiver<ControllerEvent>) = mpsc::channel();
+    thread::spawn(move || {
+        replay_thread_inner(events, tx);
+    });
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexapod::input_handler::Button;
+
+    fn event(timestamp: usize, ly: f64, triggered: Option<Button>) -> ControllerEvent {
+        let mut event = ControllerEvent { timestamp, lx: 0.3, ly, ..Default::default() };
+        if let Some(button) = triggered {
+            event.pressed[button as usize] = true;
+            event.triggered[button as usize] = true;
+        }
+        event
+    }
+
+    //stands up, walks with a rate limited stick and changes the mode back and forth mid-blend
+    fn session() -> Vec<RecordedEvent> {
+        [
+            (0, event(1, 0.0, None)),
+            (100, event(2, 0.0, Some(Button::X))),
+            (3000, event(3, 0.8, None)),
+            (5000, event(4, 0.0, Some(Button::ZR))),
+            (5100, event(5, 0.0, Some(Button::ZR))),
+            (7000, event(6, 0.0, None)),
+        ].iter().map(|(time_ms, event)| RecordedEvent { time_ms: *time_ms, event: *event }).collect()
+    }
+
+    fn config() -> Config {
+        let mut conf = Config::default();
+        conf.input_shaping.ly.max_rate = 2.0;
+        conf
+    }
+
+    #[test]
+    fn replays_are_identical() {
+        let first = replay_session(session(), config());
+        let second = replay_session(session(), config());
+        assert!(first.iter().any(|msg| msg.gait.is_some_and(|g| g.step_progress > 0.0)), "the robot didn't walk");
+
+        let to_json = |msgs: &[TelemetryMessage]| msgs.iter().map(|m| serde_json::to_string(m).unwrap()).collect::<Vec<_>>();
+        assert_eq!(to_json(&first), to_json(&second));
+    }
+}
@@ -1,34 +1,218 @@
-use std::net::{TcpStream, TcpListener};
-use hexapod::hexapod::Hexapod;
+use std::net::{SocketAddr, TcpStream, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::thread;
 use std::time::{Instant, Duration};
+use hexapod::from_name;
 use hexapod::telemetry::TelemetryMessage;
-use std::io::Write;
+use std::io::{self, BufRead, BufReader, Write};
+use crate::encoding::{Encoded, Encoding};
+use crate::topics::{detect_events, topic_message, SubscriptionRequest, Subscriptions, Topic};
+use crate::websocket::{self, ClientRequest};
 
-pub const MESSAGE_FREQUENCY: u32 = 30; //Hz
+pub const MESSAGE_FREQUENCY: u32 = 30; //Hz, the rate of the FULL topic a client starts with
+//messages that can wait for a slow client, newer ones are dropped for it while the queue is full
+const CLIENT_QUEUE_SIZE: usize = 32;
+//a client that doesn't take any data for this long is disconnected
+pub(crate) const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
 
+//sends the telemetry to every connected client over TCP and WebSocket (see listen_websocket), in
+//the encoding and with the topics and rates each client chose (see topics.rs). Clients can connect
+//and disconnect at any time, report never blocks the control loop
 pub struct TelemetryReporter{
-    socket: TcpStream,
-    lastReport: Instant,
+    new_clients: Receiver<Client>,
+    client_sender: Sender<Client>,
+    requests: Receiver<ClientRequest>,
+    request_sender: Sender<ClientRequest>,
+    clients: Vec<Client>,
+    last: Option<TelemetryMessage>, //to detect the events
 }
 
+//every client has its own thread that writes the queued messages to its socket
+pub(crate) struct Client {
+    addr: SocketAddr,
+    queue: SyncSender<Arc<Encoded>>,
+    settings: SharedSettings, //changed by the client's thread
+    dropped: u64, //messages dropped since the client last kept up
+}
+
+pub(crate) struct ClientSettings {
+    pub encoding: Encoding,
+    pub subscriptions: Subscriptions,
+}
+
+#[derive(Clone)]
+pub(crate) struct SharedSettings(Arc<Mutex<ClientSettings>>);
+
 impl TelemetryReporter {
-    pub fn new() -> Self {
-        let listener = TcpListener::bind("0.0.0.0:8766").unwrap();
-        let (mut socket, _addr) = listener.accept().unwrap();
-        println!("connected");
-
-        Self {
-            socket,
-            lastReport: Instant::now(),
-        }
+    pub fn new(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        println!("telemetry on {}", listener.local_addr()?);
+
+        let (client_sender, new_clients) = mpsc::channel();
+        let (request_sender, requests) = mpsc::channel();
+        let clients = client_sender.clone();
+        thread::spawn(move || {
+            loop {
+                match listener.accept() {
+                    Ok((socket, addr)) => {
+                        match start_tcp_client(socket, addr) {
+                            Ok(client) => if clients.send(client).is_err() { break },
+                            Err(e) => eprintln!("telemetry client {} failed: {}", addr, e),
+                        }
+                    },
+                    Err(e) => eprintln!("telemetry accept failed: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            new_clients,
+            client_sender,
+            requests,
+            request_sender,
+            clients: Vec::new(),
+            last: None,
+        })
+    }
+
+    //WebSocket clients get the same telemetry and can send commands and config patches (see requests)
+    pub fn listen_websocket(&self, addr: &str) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        println!("websocket on {}", listener.local_addr()?);
+        websocket::start_listener(listener, self.client_sender.clone(), self.request_sender.clone());
+        Ok(())
     }
 
+    //called every control tick, the topics of each client are sent when they are due
     pub fn report(&mut self, msg: &TelemetryMessage){
-        let interval = Duration::from_secs(1) / MESSAGE_FREQUENCY;
-        if self.lastReport.elapsed() > interval {
-            let message = serde_json::to_string(msg).unwrap() + "\n";
-            self.socket.write_all(message.as_bytes()).unwrap();
-            self.lastReport = Instant::now();
+        self.accept_clients();
+
+        let events = self.last.as_ref().map_or(Vec::new(), |last| detect_events(last, msg));
+        let now = Instant::now();
+        //every topic and encoding that is in use is only encoded once
+        let mut encoded: Vec<(Topic, Arc<Encoded>)> = Vec::new();
+        self.clients.retain_mut(|client| {
+            let (encoding, topics) = client.settings.due(now);
+            for topic in topics {
+                let Some(topic_message) = topic_message(topic, msg, &events) else { continue };
+                let message = match encoded.iter().find(|(t, e)| *t == topic && e.encoding == encoding) {
+                    Some((_, message)) => message.clone(),
+                    None => {
+                        let message = Arc::new(Encoded { encoding, data: encoding.encode(&topic_message) });
+                        encoded.push((topic, message.clone()));
+                        message
+                    },
+                };
+                if !client.send(message) {
+                    return false;
+                }
+            }
+            true
+        });
+        self.last = Some(msg.clone());
+    }
+
+    //the requests the WebSocket clients sent since the last call
+    pub fn requests(&self) -> impl Iterator<Item = ClientRequest> + '_ {
+        self.requests.try_iter()
+    }
+
+    fn accept_clients(&mut self) {
+        while let Ok(client) = self.new_clients.try_recv() {
+            println!("telemetry client {} connected", client.addr);
+            self.clients.push(client);
+        }
+    }
+}
+
+impl Client {
+    //the client's thread gets the messages from the returned receiver
+    pub(crate) fn new(addr: SocketAddr, settings: SharedSettings) -> (Self, Receiver<Arc<Encoded>>) {
+        let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE_SIZE);
+        (Self { addr, queue: tx, settings, dropped: 0 }, rx)
+    }
+
+    //returns false once the client has disconnected
+    fn send(&mut self, message: Arc<Encoded>) -> bool {
+        match self.queue.try_send(message) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    println!("telemetry client {} caught up, dropped {} messages", self.addr, self.dropped);
+                    self.dropped = 0;
+                }
+                true
+            },
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                true
+            },
+            Err(TrySendError::Disconnected(_)) => {
+                println!("telemetry client {} disconnected", self.addr);
+                false
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+impl SharedSettings {
+    pub(crate) fn new(encoding: Encoding, subscriptions: Subscriptions) -> Self {
+        Self(Arc::new(Mutex::new(ClientSettings { encoding, subscriptions })))
+    }
+
+    pub(crate) fn set_encoding(&self, encoding: Encoding) {
+        self.0.lock().unwrap().encoding = encoding;
+    }
+
+    pub(crate) fn subscribe(&self, request: &SubscriptionRequest) {
+        self.0.lock().unwrap().subscriptions.apply(request);
+    }
+
+    //the encoding and the topics that are due
+    fn due(&self, now: Instant) -> (Encoding, Vec<Topic>) {
+        let mut settings = self.0.lock().unwrap();
+        let topics = settings.subscriptions.due(now);
+        (settings.encoding, topics)
+    }
+}
+
+//the client can send lines at any time: the name of an encoding (e.g. "CBOR") to switch to it or a
+//json SubscriptionRequest, e.g. {"SUBSCRIBE": {"topic": "ANGLES", "rate": 100.0}}
+fn start_tcp_client(mut socket: TcpStream, addr: SocketAddr) -> io::Result<Client> {
+    socket.set_nodelay(true)?;
+    socket.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+    let reader = BufReader::new(socket.try_clone()?);
+    let settings = SharedSettings::new(Encoding::default(), Subscriptions::default());
+    let (client, messages) = Client::new(addr, settings.clone());
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            let line = line.trim();
+            if let Some(encoding) = from_name::<Encoding>(line) {
+                settings.set_encoding(encoding);
+            } else {
+                match serde_json::from_str::<SubscriptionRequest>(line) {
+                    Ok(request) => settings.subscribe(&request),
+                    Err(e) => eprintln!("telemetry client {} sent an invalid request {}: {}", addr, line, e),
+                }
+            }
+        }
+    });
+    thread::spawn(move || {
+        for message in messages {
+            if write_tcp_message(&mut socket, &message).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(client)
+}
+
+fn write_tcp_message(socket: &mut TcpStream, message: &Encoded) -> io::Result<()> {
+    if message.encoding.is_text() {
+        socket.write_all(&message.data)?;
+        socket.write_all(b"\n")
+    } else {
+        socket.write_all(&(message.data.len() as u32).to_le_bytes())?;
+        socket.write_all(&message.data)
+    }
+}
@@ -0,0 +1,248 @@
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use hexapod::telemetry::TelemetryMessage;
+
+//messages that can wait for the disk, newer ones are dropped while the queue is full
+const LOG_QUEUE_SIZE: usize = 256;
+//the files are written in larger blocks, at the latest after this long, on a rotation and when the
+//logger is dropped. A power loss only loses the messages since the last flush
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const LOG_PREFIX: &str = "telemetry-";
+const LOG_EXTENSION: &str = "jsonl";
+const INDEX_EXTENSION: &str = "idx";
+const INDEX_ENTRY_SIZE: usize = 16;
+
+//logs every telemetry message as a json line to telemetry-<n>.jsonl in a directory. Next to every
+//log file is an index (telemetry-<n>.idx) with the timestamp (f64) and byte offset (u64) of every
+//line, little-endian, so a replay can start anywhere without parsing the whole file. A new file is
+//started when the current one is full and the oldest files are deleted. The files are written by
+//their own thread, so a slow SD card doesn't stall the control loop
+pub struct TelemetryLogger {
+    queue: SyncSender<TelemetryMessage>,
+    writer: Option<JoinHandle<()>>,
+    dropped: u64,
+}
+
+struct LogWriter {
+    dir: PathBuf,
+    number: u64,
+    log: BufWriter<File>,
+    index: BufWriter<File>,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct IndexEntry {
+    pub timestamp: f64,
+    pub offset: u64,
+}
+
+impl TelemetryLogger {
+    pub fn new(dir: &str, max_size: u64, max_files: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        //a restart continues after the existing files instead of overwriting them
+        let number = log_numbers(Path::new(dir))?.last().map_or(0, |n| n + 1);
+        let mut writer = LogWriter::create(PathBuf::from(dir), number, max_size, max_files.max(1))?;
+        println!("logging telemetry to {}", writer.log_path().display());
+
+        let (tx, rx) = mpsc::sync_channel(LOG_QUEUE_SIZE);
+        let writer = thread::spawn(move || {
+            if let Err(e) = writer.run(rx) {
+                eprintln!("telemetry log failed: {}", e);
+            }
+        });
+        Ok(Self { queue: tx, writer: Some(writer), dropped: 0 })
+    }
+
+    pub fn log(&mut self, msg: &TelemetryMessage) {
+        match self.queue.try_send(msg.clone()) {
+            Ok(()) => {
+                if self.dropped > 0 {
+                    eprintln!("telemetry log caught up, dropped {} messages", self.dropped);
+                    self.dropped = 0;
+                }
+            },
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => {}, //the writer already reported its error
+        }
+    }
+}
+
+//waits for the writer to flush the queued messages, they would be lost when the program ends
+impl Drop for TelemetryLogger {
+    fn drop(&mut self) {
+        let (closed, _) = mpsc::sync_channel(0);
+        drop(mem::replace(&mut self.queue, closed));
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl LogWriter {
+    fn create(dir: PathBuf, number: u64, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let log = File::create(log_path(&dir, number, LOG_EXTENSION))?;
+        let index = File::create(log_path(&dir, number, INDEX_EXTENSION))?;
+        Ok(Self {
+            dir,
+            number,
+            log: BufWriter::new(log),
+            index: BufWriter::new(index),
+            size: 0,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn log_path(&self) -> PathBuf {
+        log_path(&self.dir, self.number, LOG_EXTENSION)
+    }
+
+    fn run(&mut self, messages: Receiver<TelemetryMessage>) -> io::Result<()> {
+        self.remove_old_files()?;
+        let mut last_flush = Instant::now();
+        loop {
+            match messages.recv_timeout(FLUSH_INTERVAL) {
+                Ok(msg) => self.write(&msg)?,
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return self.flush(),
+            }
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                self.flush()?;
+                last_flush = Instant::now();
+            }
+        }
+    }
+
+    fn write(&mut self, msg: &TelemetryMessage) -> io::Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+        let line = serde_json::to_string(msg)? + "\n";
+        self.index.write_all(&msg.timestamp.to_le_bytes())?;
+        self.index.write_all(&self.size.to_le_bytes())?;
+        self.log.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    //the log first, so the index doesn't point behind its end
+    fn flush(&mut self) -> io::Result<()> {
+        self.log.flush()?;
+        self.index.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.flush()?;
+        *self = Self::create(self.dir.clone(), self.number + 1, self.max_size, self.max_files)?;
+        self.remove_old_files()
+    }
+
+    fn remove_old_files(&self) -> io::Result<()> {
+        let numbers = log_numbers(&self.dir)?;
+        let excess = numbers.len().saturating_sub(self.max_files);
+        for number in &numbers[..excess] {
+            fs::remove_file(log_path(&self.dir, *number, LOG_EXTENSION))?;
+            let _ = fs::remove_file(log_path(&self.dir, *number, INDEX_EXTENSION));
+        }
+        Ok(())
+    }
+}
+
+fn log_path(dir: &Path, number: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{}{}.{}", LOG_PREFIX, number, extension))
+}
+
+//the numbers of the log files in a directory, oldest first
+fn log_numbers(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut numbers = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == LOG_EXTENSION) {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            if let Some(number) = stem.strip_prefix(LOG_PREFIX).and_then(|n| n.parse().ok()) {
+                numbers.push(number);
+            }
+        }
+    }
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+//a single log file or all log files of a directory, oldest first
+pub fn log_files(path: &str) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(path);
+    if path.is_dir() {
+        Ok(log_numbers(path)?.into_iter().map(|n| log_path(path, n, LOG_EXTENSION)).collect())
+    } else {
+        Ok(vec![path.to_path_buf()])
+    }
+}
+
+pub fn load_index(log: &Path) -> io::Result<Vec<IndexEntry>> {
+    let mut data = Vec::new();
+    File::open(log.with_extension(INDEX_EXTENSION))?.read_to_end(&mut data)?;
+    //a partly written last entry is ignored
+    Ok(data.chunks_exact(INDEX_ENTRY_SIZE).map(|entry| IndexEntry {
+        timestamp: f64::from_le_bytes(entry[..8].try_into().unwrap()),
+        offset: u64::from_le_bytes(entry[8..].try_into().unwrap()),
+    }).collect())
+}
+
+//the messages of a log file from the first one at or after the timestamp. Without an index the
+//whole file is read. The last line can be cut off when the robot lost power, it is skipped
+pub fn read_log(log: &Path, from: f64) -> io::Result<impl Iterator<Item = TelemetryMessage>> {
+    let mut file = File::open(log)?;
+    let mut skip_before = from;
+    match load_index(log) {
+        Ok(index) => {
+            let offset = index.iter().find(|e| e.timestamp >= from).map_or(file.metadata()?.len(), |e| e.offset);
+            file.seek(SeekFrom::Start(offset))?;
+            skip_before = f64::NEG_INFINITY;
+        },
+        Err(e) => eprintln!("no index for {}, reading the whole file: {}", log.display(), e),
+    }
+
+    let name = log.display().to_string();
+    Ok(BufReader::new(file).lines().map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(move |line| match serde_json::from_str::<TelemetryMessage>(&line) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+                eprintln!("skipping invalid line in {}: {}", name, e);
+                None
+            },
+        })
+        .filter(move |msg| msg.timestamp >= skip_before))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexapod::hexapod::Hexapod;
+
+    #[test]
+    fn writes_the_queued_messages_when_dropped() {
+        let dir = std::env::temp_dir().join(format!("telemetry-log-test-{}", std::process::id()));
+        let mut msg = Hexapod::new().get_telemetry();
+        let mut logger = TelemetryLogger::new(dir.to_str().unwrap(), 1_000_000, 2).unwrap();
+        for timestamp in 0..10 {
+            msg.timestamp = timestamp as f64;
+            logger.log(&msg);
+        }
+        drop(logger);
+
+        let files = log_files(dir.to_str().unwrap()).unwrap();
+        let timestamps = read_log(&files[0], 5.0).unwrap().map(|m| m.timestamp).collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(timestamps, vec![5.0, 6.0, 7.0, 8.0, 9.0]);
+    }
+}
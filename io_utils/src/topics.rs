@@ -0,0 +1,232 @@
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use hexapod::{from_name, GaitType};
+use hexapod::input_handler::ControllerEvent;
+use hexapod::telemetry::{GaitTelemetry, LegTelemetry, LinkState, LoopTiming, TelemetryMessage, TransformTelemetry};
+
+//the parts of the telemetry a client can subscribe to, each at its own rate. FULL is the whole
+//TelemetryMessage as before, the others are sent as {"<TOPIC>": {...}}
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Topic {
+    FULL,
+    POSE, //center, rotation and body transform
+    ANGLES, //servo angles and unreachable legs
+    LEGS, //joint, hip, knee and foot positions
+    DIAGNOSTICS, //mode, link, gait, input and loop timing
+    EVENTS, //changes of the mode, link, gait and reachability, sent when they happen
+}
+
+//sent by the clients as json, e.g. {"SUBSCRIBE": {"topic": "ANGLES", "rate": 100.0}}. Without a
+//rate the topic is sent every control tick
+#[derive(Deserialize, Debug, Clone)]
+pub enum SubscriptionRequest {
+    SUBSCRIBE { topic: Topic, rate: Option<f64> },
+    UNSUBSCRIBE(Topic),
+}
+
+//what a client gets and when it is due next
+#[derive(Debug, Clone)]
+pub struct Subscriptions {
+    topics: Vec<(Topic, Option<Duration>, Instant)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum TelemetryEvent {
+    MODE_CHANGED(String),
+    LINK_CHANGED(LinkState),
+    GAIT_CHANGED(Option<GaitType>), //None when the new mode doesn't walk
+    LEG_UNREACHABLE(usize),
+    LEG_REACHABLE(usize),
+}
+
+//one message of a topic, borrowed from the TelemetryMessage of the tick
+#[derive(Serialize, Debug)]
+pub enum TopicMessage<'a> {
+    FULL(&'a TelemetryMessage),
+    POSE(Pose<'a>),
+    ANGLES(Angles<'a>),
+    LEGS(Legs<'a>),
+    DIAGNOSTICS(Diagnostics<'a>),
+    EVENTS(Events<'a>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct Pose<'a> {
+    pub sequence: u64,
+    pub timestamp: f64,
+    pub center: &'a [f64; 2],
+    pub rotation: f64,
+    pub body: &'a TransformTelemetry,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Angles<'a> {
+    pub sequence: u64,
+    pub timestamp: f64,
+    pub angles: &'a [Option<f64>; 6*3],
+    pub unreachable: &'a [bool; 6],
+}
+
+#[derive(Serialize, Debug)]
+pub struct Legs<'a> {
+    pub sequence: u64,
+    pub timestamp: f64,
+    pub legs: &'a [LegTelemetry; 6],
+}
+
+#[derive(Serialize, Debug)]
+pub struct Diagnostics<'a> {
+    pub sequence: u64,
+    pub timestamp: f64,
+    pub mode: &'a str,
+    pub link: LinkState,
+    pub gait: &'a Option<GaitTelemetry>,
+    pub input: &'a ControllerEvent,
+    pub timing: &'a LoopTiming,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Events<'a> {
+    pub sequence: u64,
+    pub timestamp: f64,
+    pub events: &'a [TelemetryEvent],
+}
+
+impl Topic {
+    pub(crate) fn id(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl Default for Subscriptions {
+    //the full message at the rate every client got before there were topics
+    fn default() -> Self {
+        let mut subscriptions = Self { topics: Vec::new() };
+        subscriptions.subscribe(Topic::FULL, Some(crate::telemetry::MESSAGE_FREQUENCY as f64));
+        subscriptions
+    }
+}
+
+impl Subscriptions {
+    pub fn none() -> Self {
+        Self { topics: Vec::new() }
+    }
+
+    //parses a list like "ANGLES,POSE:20", the rate is in Hz and every tick without one
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut subscriptions = Self::none();
+        for entry in list.split(',').filter(|e| !e.is_empty()) {
+            let (name, rate) = match entry.split_once(':') {
+                Some((name, rate)) => (name, Some(rate.parse::<f64>().map_err(|_| format!("invalid rate {}", rate))?)),
+                None => (entry, None),
+            };
+            let topic = from_name::<Topic>(name).ok_or(format!("unknown topic {}", name))?;
+            subscriptions.subscribe(topic, rate);
+        }
+        Ok(subscriptions)
+    }
+
+    pub fn apply(&mut self, request: &SubscriptionRequest) {
+        match *request {
+            SubscriptionRequest::SUBSCRIBE { topic, rate } => self.subscribe(topic, rate),
+            SubscriptionRequest::UNSUBSCRIBE(topic) => self.topics.retain(|(t, _, _)| *t != topic),
+        }
+    }
+
+    fn subscribe(&mut self, topic: Topic, rate: Option<f64>) {
+        self.topics.retain(|(t, _, _)| *t != topic);
+        let interval = rate.filter(|r| *r > 0.0).map(|r| Duration::from_secs_f64(1.0 / r));
+        self.topics.push((topic, interval, Instant::now()));
+    }
+
+    //the topics that are due this tick. EVENTS are always due, there is only something to send
+    //when an event happened
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<Topic> {
+        let mut due = Vec::new();
+        for (topic, interval, next) in self.topics.iter_mut() {
+            if *topic != Topic::EVENTS {
+                if now < *next {
+                    continue;
+                }
+                //the schedule is kept so the rate doesn't drift with the loop period, unless the
+                //client fell behind
+                if let Some(interval) = interval {
+                    *next = (*next + *interval).max(now);
+                }
+            }
+            due.push(*topic);
+        }
+        due
+    }
+}
+
+impl TopicMessage<'_> {
+    pub fn topic(&self) -> Topic {
+        match self {
+            TopicMessage::FULL(_) => Topic::FULL,
+            TopicMessage::POSE(_) => Topic::POSE,
+            TopicMessage::ANGLES(_) => Topic::ANGLES,
+            TopicMessage::LEGS(_) => Topic::LEGS,
+            TopicMessage::DIAGNOSTICS(_) => Topic::DIAGNOSTICS,
+            TopicMessage::EVENTS(_) => Topic::EVENTS,
+        }
+    }
+
+    //the sequence and timestamp of the TelemetryMessage it came from
+    pub fn stamp(&self) -> (u64, f64) {
+        match self {
+            TopicMessage::FULL(m) => (m.sequence, m.timestamp),
+            TopicMessage::POSE(m) => (m.sequence, m.timestamp),
+            TopicMessage::ANGLES(m) => (m.sequence, m.timestamp),
+            TopicMessage::LEGS(m) => (m.sequence, m.timestamp),
+            TopicMessage::DIAGNOSTICS(m) => (m.sequence, m.timestamp),
+            TopicMessage::EVENTS(m) => (m.sequence, m.timestamp),
+        }
+    }
+}
+
+//the message of a topic for this tick, None for EVENTS when nothing happened
+pub fn topic_message<'a>(topic: Topic, msg: &'a TelemetryMessage, events: &'a [TelemetryEvent]) -> Option<TopicMessage<'a>> {
+    let (sequence, timestamp) = (msg.sequence, msg.timestamp);
+    Some(match topic {
+        Topic::FULL => TopicMessage::FULL(msg),
+        Topic::POSE => TopicMessage::POSE(Pose { sequence, timestamp, center: &msg.center, rotation: msg.rotation, body: &msg.body }),
+        Topic::ANGLES => TopicMessage::ANGLES(Angles { sequence, timestamp, angles: &msg.angles, unreachable: &msg.unreachable }),
+        Topic::LEGS => TopicMessage::LEGS(Legs { sequence, timestamp, legs: &msg.legs }),
+        Topic::DIAGNOSTICS => TopicMessage::DIAGNOSTICS(Diagnostics {
+            sequence,
+            timestamp,
+            mode: &msg.mode,
+            link: msg.link,
+            gait: &msg.gait,
+            input: &msg.input,
+            timing: &msg.timing,
+        }),
+        Topic::EVENTS if events.is_empty() => return None,
+        Topic::EVENTS => TopicMessage::EVENTS(Events { sequence, timestamp, events }),
+    })
+}
+
+//the events between two consecutive messages
+pub fn detect_events(last: &TelemetryMessage, msg: &TelemetryMessage) -> Vec<TelemetryEvent> {
+    let mut events = Vec::new();
+    if last.mode != msg.mode {
+        events.push(TelemetryEvent::MODE_CHANGED(msg.mode.clone()));
+    }
+    if last.link != msg.link {
+        events.push(TelemetryEvent::LINK_CHANGED(msg.link));
+    }
+    let gait = msg.gait.map(|g| g.gait);
+    if last.gait.map(|g| g.gait) != gait {
+        events.push(TelemetryEvent::GAIT_CHANGED(gait));
+    }
+    for (leg, (was, is)) in last.unreachable.iter().zip(msg.unreachable.iter()).enumerate() {
+        match (was, is) {
+            (false, true) => events.push(TelemetryEvent::LEG_UNREACHABLE(leg)),
+            (true, false) => events.push(TelemetryEvent::LEG_REACHABLE(leg)),
+            _ => {},
+        }
+    }
+    events
+}
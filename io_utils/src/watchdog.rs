@@ -0,0 +1,159 @@
+use std::sync::mpsc::Receiver;
+use std::time::Instant;
+use hexapod::config::Config;
+use hexapod::input_handler::ControllerEvent;
+use hexapod::telemetry::LinkState;
+
+//watches the controller events. When none arrive within conf.controller_timeout the link counts
+//as lost, all buttons are released and the sticks fade to zero over conf.controller_fade_time
+pub struct Watchdog {
+    receiver: Receiver<ControllerEvent>,
+    last_event: ControllerEvent,
+    last_received: Instant,
+    link: LinkState,
+}
+
+impl Watchdog {
+    pub fn new(receiver: Receiver<ControllerEvent>) -> Self {
+        Self {
+            receiver,
+            last_event: ControllerEvent::default(),
+            last_received: Instant::now(),
+            link: LinkState::WAITING,
+        }
+    }
+
+    //takes every event that arrived since the last call, so the queue can't grow when the
+    //controller sends faster than the control loop runs. They are merged (see ControllerEvent::merge)
+    //into the event the robot acts on and returned for the recording
+    pub fn receive(&mut self, conf: &Config) -> Vec<ControllerEvent> {
+        let events = self.receiver.try_iter().collect::<Vec<_>>();
+        match ControllerEvent::merge(&events) {
+            Some(event) => {
+                if self.link != LinkState::CONNECTED {
+                    println!("controller link established");
+                }
+                self.link = LinkState::CONNECTED;
+                self.last_event = event;
+                self.last_received = Instant::now();
+            },
+            //a closed channel (e.g. the end of a replay) times out like a dropped connection
+            None => {
+                let timed_out = self.last_received.elapsed().as_secs_f64() > conf.controller_timeout;
+                if self.link == LinkState::CONNECTED && timed_out {
+                    eprintln!("controller link lost");
+                    self.link = LinkState::LOST;
+                }
+            },
+        }
+        events
+    }
+
+    //the event the robot should act on
+    pub fn event(&self, conf: &Config) -> ControllerEvent {
+        if self.link != LinkState::LOST {
+            return self.last_event;
+        }
+        let scale = 1.0 - (self.lost_for(conf) / conf.controller_fade_time).min(1.0);
+        ControllerEvent {
+            timestamp: self.last_event.timestamp,
+            lx: self.last_event.lx * scale,
+            ly: self.last_event.ly * scale,
+            rx: self.last_event.rx * scale,
+            ry: self.last_event.ry * scale,
+            lt: self.last_event.lt * scale,
+            rt: self.last_event.rt * scale,
+            dpad_x: self.last_event.dpad_x * scale,
+            dpad_y: self.last_event.dpad_y * scale,
+            ..Default::default()
+        }
+    }
+
+    //true once the sticks have faded out, the robot should settle into a safe pose then
+    pub fn should_settle(&self, conf: &Config) -> bool {
+        self.link == LinkState::LOST && self.lost_for(conf) >= conf.controller_fade_time
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.link
+    }
+
+    //seconds since the link counts as lost
+    fn lost_for(&self, conf: &Config) -> f64 {
+        (self.last_received.elapsed().as_secs_f64() - conf.controller_timeout).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use hexapod::input_handler::Button;
+
+    fn event(timestamp: usize, lx: f64, triggered: &[Button]) -> ControllerEvent {
+        let mut event = ControllerEvent { timestamp, lx, ly: -lx, ..Default::default() };
+        for button in triggered {
+            event.pressed[*button as usize] = true;
+            event.triggered[*button as usize] = true;
+        }
+        event
+    }
+
+    //pretends the last event arrived that long ago
+    fn backdate(watchdog: &mut Watchdog, seconds: f64) {
+        watchdog.last_received = Instant::now() - Duration::from_secs_f64(seconds);
+    }
+
+    #[test]
+    fn fades_out_after_the_link_was_lost() {
+        let conf = Config { controller_timeout: 0.1, controller_fade_time: 0.2, ..Config::default() };
+        let (sender, receiver) = mpsc::channel();
+        let mut watchdog = Watchdog::new(receiver);
+        backdate(&mut watchdog, 1.0);
+        assert!(watchdog.receive(&conf).is_empty());
+        assert_eq!(watchdog.link_state(), LinkState::WAITING);
+
+        sender.send(event(1, 1.0, &[Button::A])).unwrap();
+        assert_eq!(watchdog.receive(&conf).len(), 1);
+        assert_eq!(watchdog.link_state(), LinkState::CONNECTED);
+        assert_eq!(watchdog.event(&conf).lx, 1.0);
+
+        for fade in [0.25, 0.75].iter() {
+            backdate(&mut watchdog, conf.controller_timeout + fade * conf.controller_fade_time);
+            watchdog.receive(&conf);
+            assert_eq!(watchdog.link_state(), LinkState::LOST);
+            let event = watchdog.event(&conf);
+            assert!((event.lx - (1.0 - fade)).abs() < 0.01, "{} after {} of the fade", event.lx, fade);
+            assert!((event.ly + (1.0 - fade)).abs() < 0.01, "{} after {} of the fade", event.ly, fade);
+            assert!(!event.pressed(Button::A) && !event.triggered(Button::A));
+            assert!(!watchdog.should_settle(&conf));
+        }
+
+        backdate(&mut watchdog, conf.controller_timeout + conf.controller_fade_time + 0.01);
+        assert_eq!(watchdog.event(&conf).lx, 0.0);
+        assert!(watchdog.should_settle(&conf));
+
+        sender.send(event(2, 0.5, &[])).unwrap();
+        watchdog.receive(&conf);
+        assert_eq!(watchdog.link_state(), LinkState::CONNECTED);
+        assert!(!watchdog.should_settle(&conf));
+        assert_eq!(watchdog.event(&conf).lx, 0.5);
+    }
+
+    #[test]
+    fn keeps_the_presses_of_all_queued_events() {
+        let conf = Config::default();
+        let (sender, receiver) = mpsc::channel();
+        let mut watchdog = Watchdog::new(receiver);
+        sender.send(event(1, 0.1, &[])).unwrap();
+        sender.send(event(2, 0.2, &[Button::B])).unwrap();
+        sender.send(event(3, 0.3, &[])).unwrap();
+
+        assert_eq!(watchdog.receive(&conf).iter().map(|e| e.timestamp).collect::<Vec<_>>(), vec![1, 2, 3]);
+        let event = watchdog.event(&conf);
+        assert_eq!((event.timestamp, event.lx), (3, 0.3));
+        assert!(event.triggered(Button::B));
+        assert!(watchdog.receive(&conf).is_empty());
+    }
+}
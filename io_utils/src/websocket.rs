@@ -0,0 +1,178 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use serde::Deserialize;
+use tungstenite::{http, Message, WebSocket};
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use hexapod::from_name;
+use hexapod::command::Command;
+use crate::encoding::{Encoded, Encoding};
+use crate::telemetry::{Client, SharedSettings, CLIENT_WRITE_TIMEOUT};
+use crate::topics::{SubscriptionRequest, Subscriptions};
+
+//how long a client thread waits for a request before it sends the queued telemetry
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+//what a WebSocket client can send as a json text message besides a SubscriptionRequest, e.g.
+//{"COMMAND": {"WALK": {"vx": 0.0, "vy": 0.5, "omega": 0.0}}} or {"CONFIG": {"anim_timestep": 0.04}}
+#[derive(Deserialize, Debug)]
+pub enum ClientRequest {
+    COMMAND(Command),
+    CONFIG(serde_json::Value), //merged into the config with Config::patch
+}
+
+pub(crate) fn start_listener(listener: TcpListener, clients: Sender<Client>, requests: Sender<ClientRequest>) {
+    accept_clients(listener, "websocket", move |stream, addr| run_client(stream, addr, clients.clone(), requests.clone()));
+}
+
+//every client is served by its own thread, name is the server in the log messages
+pub(crate) fn accept_clients<F>(listener: TcpListener, name: &'static str, serve: F)
+where F: Fn(TcpStream, SocketAddr) + Clone + Send + 'static {
+    thread::spawn(move || {
+        loop {
+            match listener.accept() {
+                Ok((stream, addr)) => {
+                    let serve = serve.clone();
+                    thread::spawn(move || serve(stream, addr));
+                },
+                Err(e) => eprintln!("{} accept failed: {}", name, e),
+            }
+        }
+    });
+}
+
+//the handshake, the callback can look at the request and change the response. None when the
+//client couldn't be set up, that was already logged
+pub(crate) fn accept<C: Callback>(stream: TcpStream, addr: SocketAddr, name: &str, callback: C) -> Option<WebSocket<TcpStream>> {
+    let socket = match tungstenite::accept_hdr(stream, callback) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("{} handshake with {} failed: {}", name, addr, e);
+            return None;
+        },
+    };
+    if let Err(e) = set_timeouts(&socket) {
+        eprintln!("{} client {} failed: {}", name, addr, e);
+        return None;
+    }
+    Some(socket)
+}
+
+//alternates between sending what is queued for the client and waiting up to POLL_INTERVAL for
+//one of its text messages. Ends when the client leaves or send or handle_text fail
+pub(crate) fn serve_client<S, H>(socket: &mut WebSocket<TcpStream>, mut send: S, mut handle_text: H)
+where S: FnMut(&mut WebSocket<TcpStream>) -> Result<(), ()>,
+      H: FnMut(&mut WebSocket<TcpStream>, String) -> Result<(), ()> {
+    while let Ok(()) = send(socket) {
+        match socket.read() {
+            Ok(Message::Text(text)) => if handle_text(socket, text).is_err() {
+                break;
+            },
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {},
+            Err(tungstenite::Error::Io(e)) if is_timeout(&e) => {},
+            Err(_) => break,
+        }
+    }
+}
+
+//one thread per client reads its requests and writes the telemetry. The encoding and the topics
+//are chosen with the query of the url, e.g. ws://hexapod:8767/?encoding=CBOR&topics=ANGLES,POSE:20
+//(see Subscriptions::parse), JSON is sent as text messages and the others as binary ones. When a
+//client that sent commands disconnects the robot stops, so it doesn't keep walking without anyone
+//in control
+fn run_client(stream: TcpStream, addr: SocketAddr, clients: Sender<Client>, requests: Sender<ClientRequest>) {
+    let mut encoding = Encoding::default();
+    let mut subscriptions = Subscriptions::default();
+    #[allow(clippy::result_large_err)] //the signature is given by tungstenite
+    let choose_settings = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        let query = request.uri().query().unwrap_or("");
+        let param = |name: &str| query.split('&').find_map(|p| p.strip_prefix(name)?.strip_prefix('='));
+        if let Some(name) = param("encoding") {
+            match from_name::<Encoding>(name) {
+                Some(e) => encoding = e,
+                None => return Err(bad_request(format!("unknown encoding {}", name))),
+            }
+        }
+        if let Some(list) = param("topics") {
+            match Subscriptions::parse(list) {
+                Ok(s) => subscriptions = s,
+                Err(e) => return Err(bad_request(e)),
+            }
+        }
+        Ok(response)
+    };
+    let mut socket = match accept(stream, addr, "websocket", choose_settings) {
+        Some(socket) => socket,
+        None => return,
+    };
+    let settings = SharedSettings::new(encoding, subscriptions);
+    let (client, messages) = Client::new(addr, settings.clone());
+    if clients.send(client).is_err() {
+        return;
+    }
+
+    let mut commanded = false;
+    serve_client(&mut socket, |socket| send_telemetry(socket, &messages), |socket, text| {
+        if is_subscription(&text) {
+            settings.subscribe(&serde_json::from_str::<SubscriptionRequest>(&text).unwrap());
+            return Ok(());
+        }
+        match serde_json::from_str::<ClientRequest>(&text) {
+            Ok(request) => {
+                commanded |= matches!(request, ClientRequest::COMMAND(_));
+                requests.send(request).map_err(|_| ())
+            },
+            Err(e) => {
+                let reply = serde_json::json!({ "error": format!("invalid request: {}", e) });
+                socket.send(Message::Text(reply.to_string())).map_err(|_| ())
+            },
+        }
+    });
+
+    if commanded {
+        let _ = requests.send(ClientRequest::COMMAND(Command::STOP));
+    }
+}
+
+fn is_subscription(text: &str) -> bool {
+    serde_json::from_str::<SubscriptionRequest>(text).is_ok()
+}
+
+fn bad_request(message: String) -> ErrorResponse {
+    let mut error = ErrorResponse::new(Some(message));
+    *error.status_mut() = http::StatusCode::BAD_REQUEST;
+    error
+}
+
+fn set_timeouts(socket: &WebSocket<TcpStream>) -> io::Result<()> {
+    let stream = socket.get_ref();
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))
+}
+
+//fails once the reporter has dropped the client or the client doesn't take the data
+fn send_telemetry(socket: &mut WebSocket<TcpStream>, messages: &Receiver<Arc<Encoded>>) -> Result<(), ()> {
+    loop {
+        match messages.try_recv() {
+            Ok(message) => {
+                let message = if message.encoding.is_text() {
+                    Message::Text(String::from_utf8_lossy(&message.data).into_owned())
+                } else {
+                    Message::Binary(message.data.clone())
+                };
+                socket.send(message).map_err(|_| ())?
+            },
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(TryRecvError::Disconnected) => return Err(()),
+        }
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
@@ -1,51 +1,108 @@
-use io_utils::controller;
+use io_utils::args::{arg_value, load_config};
+use io_utils::foxglove::FoxgloveServer;
+use io_utils::input;
+use io_utils::recording::{self, SessionRecorder};
 use io_utils::telemetry::TelemetryReporter;
+use io_utils::telemetry_log::TelemetryLogger;
+use io_utils::watchdog::Watchdog;
+use io_utils::websocket::ClientRequest;
 
-use hexapod::hexapod::{Hexapod, Foot};
+use hexapod::hexapod::Hexapod;
 
+use std::env;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
-use pwm_pca9685::{Pca9685, Address, Channel};
-use rppal::i2c::I2c;
-use hexapod::config::Config;
-use hexapod::input_handler::{Button, ControllerEvent, InputHandler};
+use hexapod::input_handler::InputHandler;
+use hexapod::telemetry::LoopTiming;
 use crate::servo_controller::ServoController;
 
 mod servo_controller;
 
 fn main() {
-    let receiver = controller::start_controller_thread("pop-os.fritz.box:8765");
+    //--record <file> saves the controller events, --replay <file> plays them back instead of the controller,
+    //--config <file> overrides the default configuration and --input <spec> the input source from it,
+    //--log-telemetry <dir> the directory the telemetry is logged to
+    let args: Vec<String> = env::args().collect();
+    let mut conf = load_config(&args).unwrap();
 
-    let mut telemetry = TelemetryReporter::new();
+    let receiver = match arg_value(&args, "--replay") {
+        Some(path) => recording::start_replay_thread(path).unwrap(),
+        None => input::start_input_thread(input::parse_source(arg_value(&args, "--input").unwrap_or(&conf.input), &conf).unwrap()),
+    };
+    let mut recorder = arg_value(&args, "--record").map(|path| SessionRecorder::new(path).unwrap());
 
-    let mut last_event = ControllerEvent::default();
+    let mut telemetry = TelemetryReporter::new(&conf.telemetry_address).unwrap();
+    telemetry.listen_websocket(&conf.websocket_address).unwrap();
+    let mut foxglove = FoxgloveServer::new(&conf.foxglove_address, conf.foxglove_publish_rate).unwrap();
+    let mut telemetry_log = arg_value(&args, "--log-telemetry").or(conf.telemetry_log.as_ref())
+        .map(|dir| TelemetryLogger::new(dir, conf.telemetry_log_file_size, conf.telemetry_log_files).unwrap());
+
+    let mut watchdog = Watchdog::new(receiver);
     let mut hexapod = Hexapod::new();
-    let mut input_handler = InputHandler::new();
+    let mut input_handler = InputHandler::new(&conf);
 
     let mut servos_left = ServoController::new(true);
     let mut servos_right = ServoController::new(false);
-
-    let mut conf = Config::default();
+    let mut timing = LoopTiming::default();
+    let mut last_tick = Instant::now();
+    let mut next_tick = Instant::now();
 
     loop {
-        if let Ok(event) = receiver.try_recv() {
-            if event.triggered(Button::B) {
-                servos_left.release();
-                servos_right.release();
-                break;
+        let tick = Instant::now();
+        timing.period = tick.duration_since(last_tick).as_secs_f64();
+        last_tick = tick;
+
+        for event in watchdog.receive(&conf) {
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&event).unwrap();
             }
-            last_event = event;
         }
 
-        input_handler.handle_input(last_event, &mut hexapod, &mut conf);
+        for request in telemetry.requests() {
+            match request {
+                ClientRequest::COMMAND(command) => input_handler.handle_command(&command, &mut hexapod, &conf),
+                ClientRequest::CONFIG(patch) => {
+                    if let Err(e) = conf.patch(&patch) {
+                        eprintln!("config patch failed: {}", e);
+                    }
+                },
+            }
+        }
 
-        telemetry.report(&hexapod.get_telemetry());
+        if input_handler.should_exit() {
+            servos_left.release();
+            servos_right.release();
+            break;
+        }
+
+        //without a controller the robot keeps standing in a safe pose until the link is back
+        if watchdog.should_settle(&conf) {
+            input_handler.settle(&mut hexapod, &mut conf);
+        } else {
+            input_handler.handle_input(watchdog.event(&conf), &mut hexapod, &mut conf);
+        }
+
+        let mut message = input_handler.get_telemetry(&mut hexapod);
+        message.link = watchdog.link_state();
+        message.timing = timing;
+        telemetry.report(&message);
+        foxglove.publish(&message);
+        if let Some(log) = telemetry_log.as_mut() {
+            log.log(&message);
+        }
 
         let angles = hexapod.get_angles();
         servos_right.set_angles(&angles[..9]);
         servos_left.set_angles(&angles[9..]);
+        timing.busy = tick.elapsed().as_secs_f64();
 
-        sleep(Duration::from_millis(10));
+        //the ticks are conf.tick_period apart, the modes count time in ticks. After an overrun
+        //the schedule starts over instead of catching up with shorter ticks
+        next_tick += Duration::from_secs_f64(conf.tick_period);
+        match next_tick.checked_duration_since(Instant::now()) {
+            Some(remaining) => sleep(remaining),
+            None => next_tick = Instant::now(),
+        }
     }
 }
\ No newline at end of file
@@ -2,13 +2,13 @@ use pwm_pca9685::{Pca9685, Address, Channel};
 use rppal::i2c::I2c;
 use std::convert::TryFrom;
 use std::f64::consts::PI;
+use hexapod::config::SERVO_LIMITS;
 
 const MIN_PULSE: u16 = 170;
 const MAX_PULSE: u16 = 480;
-const RADIANS_TO_PULSE: f64 = 380 as f64 / PI;
+const RADIANS_TO_PULSE: f64 = 380.0 / PI;
 
 // the servos are always in the order [hip_xy, hip_z, knee]
-const SERVO_LIMITS: [(f64, f64); 3] = [(-PI*0.25, PI*0.25), (-PI*0.33, PI*0.4), (-PI*0.28, PI*0.45)];
 const SERVO_INVERT: [bool; 3] = [false, true, false];
 
 // the legs are in the order [right_front, right_middle, right_back], [left_back, left_middle, left_front]
@@ -61,7 +61,7 @@ impl ServoController {
         if SERVO_INVERT[servo % 3] { angle *= -1.0; }
         let pulse = (self.calibration[servo] as i16 + ((RADIANS_TO_PULSE * angle) as i16)) as u16;
 
-        if pulse < MIN_PULSE || pulse > MAX_PULSE {
+        if !(MIN_PULSE..=MAX_PULSE).contains(&pulse) {
             eprintln!("pulse out of range! (servo {} (type: {}), pulse {})", servo, servo%3, pulse);
             return;
         }